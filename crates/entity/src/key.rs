@@ -1,17 +1,207 @@
-use sea_orm::entity::prelude::*;
+use std::collections::BTreeMap;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+use sea_orm::{entity::prelude::*, FromJsonQueryResult, Set};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "tokens", rename_all = "camelCase")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     #[sea_orm(indexed, nullable)]
     pub user_id: i64,
+    /// 唯一索引由迁移创建
     #[sea_orm(indexed, nullable)]
     pub key: String,
+    #[sea_orm(default_value = "random")]
+    pub strategy: Strategy,
+    /// 一致性哈希使用的请求属性，如 `ip`、`header:X-User`、`query:uid`、`cookie:sid`
+    #[sea_orm(nullable)]
+    pub hash_on: Option<String>,
+    #[sea_orm(default_value = "off")]
+    pub sticky: Sticky,
+    #[sea_orm(default_value = "redirect")]
+    pub mode: Mode,
+    #[sea_orm(default_value = "append")]
+    pub path_mode: PathMode,
+    /// 跳转使用的状态码：301、302、303、307 或 308
+    #[sea_orm(default_value = 307)]
+    pub redirect_status: i16,
+    /// 分配到灰度分组的流量百分比
+    #[sea_orm(default_value = 0)]
+    pub canary_percent: i16,
+    /// 同一客户端是否固定落在同一侧
+    #[sea_orm(default_value = false)]
+    pub canary_sticky: bool,
+    /// 按顺序匹配的路由规则，命中后使用规则指定的分组
+    #[sea_orm(column_type = "Json", nullable)]
+    pub rules: Option<Rules>,
+    /// 显示名称
+    #[sea_orm(nullable)]
+    pub name: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    #[sea_orm(column_type = "Json", nullable)]
+    pub tags: Option<Tags>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created_at: DateTimeUtc,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub updated_at: DateTimeUtc,
+    /// 过期时间，过期后不再跳转
+    #[sea_orm(nullable)]
+    pub expires_at: Option<DateTimeUtc>,
+    /// 最多跳转次数，计数保存在 Redis
+    #[sea_orm(nullable)]
+    pub max_hits: Option<i64>,
+    /// 过期、次数用尽、被禁用或没有可用 url 时跳转的地址
+    #[sea_orm(column_type = "Text", nullable)]
+    pub fallback_url: Option<String>,
+    /// 禁用后不再选择 url，直接使用备用地址
+    #[sea_orm(default_value = false)]
+    pub disabled: bool,
+    /// 访问密码的 argon2 哈希（PHC 格式），为空表示不需要密码
+    #[sea_orm(nullable)]
+    pub password: Option<String>,
+}
+
+/// 负载均衡策略
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// 按权重随机
+    #[default]
+    #[sea_orm(string_value = "random")]
+    Random,
+    /// 严格轮询，忽略权重
+    #[sea_orm(string_value = "round_robin")]
+    RoundRobin,
+    /// 平滑加权轮询
+    #[sea_orm(string_value = "weighted")]
+    Weighted,
+    /// 按请求属性一致性哈希
+    #[sea_orm(string_value = "consistent_hash")]
+    ConsistentHash,
+}
+
+/// 会话保持方式
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Sticky {
+    #[default]
+    #[sea_orm(string_value = "off")]
+    Off,
+    /// 按客户端地址哈希
+    #[sea_orm(string_value = "ip")]
+    Ip,
+    /// 首次跳转时写入 cookie
+    #[sea_orm(string_value = "cookie")]
+    Cookie,
+}
+
+/// 访问方式：跳转到后端或由服务代理请求
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    #[sea_orm(string_value = "redirect")]
+    Redirect,
+    #[sea_orm(string_value = "proxy")]
+    Proxy,
+}
+
+/// key 之后的路径和查询参数如何拼接到后端 url
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum PathMode {
+    /// 追加到后端 url 的路径和查询参数之后
+    #[default]
+    #[sea_orm(string_value = "append")]
+    Append,
+    /// 替换后端 url 的路径和查询参数
+    #[sea_orm(string_value = "replace")]
+    Replace,
+    /// 丢弃，始终使用后端 url 本身
+    #[sea_orm(string_value = "ignore")]
+    Ignore,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct Rules(pub Vec<Rule>);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// 全部满足时命中，为空时总是命中
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub pool: String,
+    /// 只使用分组中带有这些属性的 url
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Condition {
+    pub field: Field,
+    /// `header` 和 `query` 需要指定名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub op: Op,
+    #[serde(default)]
+    pub value: String,
+}
+
+/// 规则匹配的请求属性
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    UserAgent,
+    AcceptLanguage,
+    Header,
+    Query,
+    Method,
+}
+
+/// 匹配方式，均不区分大小写
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Equals,
+    Contains,
+    Prefix,
+    Suffix,
+    Exists,
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct Tags(pub Vec<String>);
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
-impl ActiveModelBehavior for ActiveModel {}
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// 写入时维护创建和更新时间
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = chrono::Utc::now();
+        if insert {
+            self.created_at = Set(now);
+        }
+        self.updated_at = Set(now);
+        Ok(self)
+    }
+}
@@ -1,14 +1,79 @@
-use sea_orm::entity::prelude::*;
+use std::collections::BTreeMap;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+use sea_orm::{entity::prelude::*, FromJsonQueryResult};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "urls", rename_all = "camelCase")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     #[sea_orm(primary_key)]
     pub url: String,
+    /// 负载均衡权重，按比例分配流量，0 表示不参与选择
+    #[sea_orm(default_value = 1)]
+    pub weight: i32,
+    /// 优先级，数值越小越优先，只有更优先的一组全部不可用时才会使用下一组
+    #[sea_orm(default_value = 0)]
+    pub priority: i32,
+    /// 所属分组，用于灰度发布和路由规则
+    #[sea_orm(default_value = "default")]
+    pub pool: String,
+    /// 生效时间窗口，窗口外不参与选择
+    #[sea_orm(column_type = "Json", nullable)]
+    pub schedule: Option<Schedule>,
+    /// 停用后不参与选择，但保留在列表中
+    #[sea_orm(default_value = false)]
+    pub disabled: bool,
+    /// 便于识别的标签，如 `hk-mirror`
+    #[sea_orm(nullable)]
+    pub label: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub notes: Option<String>,
+    /// 自定义属性，如地区、供应商，可被路由规则引用
+    #[sea_orm(column_type = "Json", nullable)]
+    pub attributes: Option<Attributes>,
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct Attributes(pub BTreeMap<String, String>);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct Schedule {
+    /// 开始生效的 unix 时间戳（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    /// 停止生效的 unix 时间戳（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
+    /// 每周重复的生效时段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly: Option<Weekly>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Weekly {
+    /// 星期几生效，1 表示周一，7 表示周日，为空时每天生效
+    #[serde(default)]
+    pub days: Vec<u8>,
+    /// 每天的开始时间，`HH:MM`
+    pub from: String,
+    /// 每天的结束时间，`HH:MM`，早于开始时间时表示跨越午夜
+    pub to: String,
+    /// IANA 时区名，例如 `Asia/Shanghai`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+/// 未指定分组的 url 所在的稳定分组
+pub const DEFAULT_POOL: &str = "default";
+/// 灰度分组
+pub const CANARY_POOL: &str = "canary";
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
@@ -1,12 +1,49 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261018_000002_add_url_weight;
+mod m20261018_000003_add_key_strategy;
+mod m20261018_000004_add_key_sticky;
+mod m20261018_000005_add_key_mode;
+mod m20261018_000006_add_key_path_mode;
+mod m20261018_000007_add_key_redirect_status;
+mod m20261018_000008_add_url_priority;
+mod m20261018_000009_add_canary;
+mod m20261018_000010_add_key_rules;
+mod m20261018_000011_add_url_schedule;
+mod m20261018_000012_add_url_disabled;
+mod m20261018_000013_unique_key;
+mod m20261018_000014_add_key_metadata;
+mod m20261018_000015_add_url_metadata;
+mod m20261018_000016_add_key_limits;
+mod m20261018_000017_add_key_disabled;
+mod m20261018_000018_add_key_password;
+mod util;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261018_000002_add_url_weight::Migration),
+            Box::new(m20261018_000003_add_key_strategy::Migration),
+            Box::new(m20261018_000004_add_key_sticky::Migration),
+            Box::new(m20261018_000005_add_key_mode::Migration),
+            Box::new(m20261018_000006_add_key_path_mode::Migration),
+            Box::new(m20261018_000007_add_key_redirect_status::Migration),
+            Box::new(m20261018_000008_add_url_priority::Migration),
+            Box::new(m20261018_000009_add_canary::Migration),
+            Box::new(m20261018_000010_add_key_rules::Migration),
+            Box::new(m20261018_000011_add_url_schedule::Migration),
+            Box::new(m20261018_000012_add_url_disabled::Migration),
+            Box::new(m20261018_000013_unique_key::Migration),
+            Box::new(m20261018_000014_add_key_metadata::Migration),
+            Box::new(m20261018_000015_add_url_metadata::Migration),
+            Box::new(m20261018_000016_add_key_limits::Migration),
+            Box::new(m20261018_000017_add_key_disabled::Migration),
+            Box::new(m20261018_000018_add_key_password::Migration),
+        ]
     }
 }
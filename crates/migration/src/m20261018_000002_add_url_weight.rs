@@ -0,0 +1,17 @@
+use sea_orm_migration::prelude::*;
+
+use crate::util::{add_missing_columns, drop_columns};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_missing_columns(manager, entity::url::Entity, &[entity::url::Column::Weight]).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_columns(manager, entity::url::Entity, &[entity::url::Column::Weight]).await
+    }
+}
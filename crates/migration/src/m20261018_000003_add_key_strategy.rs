@@ -0,0 +1,20 @@
+use sea_orm_migration::prelude::*;
+
+use crate::util::{add_missing_columns, drop_columns};
+
+const COLUMNS: [entity::key::Column; 2] =
+    [entity::key::Column::Strategy, entity::key::Column::HashOn];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_missing_columns(manager, entity::key::Entity, &COLUMNS).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_columns(manager, entity::key::Entity, &COLUMNS).await
+    }
+}
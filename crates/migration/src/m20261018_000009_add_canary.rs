@@ -0,0 +1,24 @@
+use sea_orm_migration::prelude::*;
+
+use crate::util::{add_missing_columns, drop_columns};
+
+const KEY_COLUMNS: [entity::key::Column; 2] = [
+    entity::key::Column::CanaryPercent,
+    entity::key::Column::CanarySticky,
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_missing_columns(manager, entity::key::Entity, &KEY_COLUMNS).await?;
+        add_missing_columns(manager, entity::url::Entity, &[entity::url::Column::Pool]).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_columns(manager, entity::key::Entity, &KEY_COLUMNS).await?;
+        drop_columns(manager, entity::url::Entity, &[entity::url::Column::Pool]).await
+    }
+}
@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+const INDEX: &str = "idx-tokens-key";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// 自定义 key 由用户指定，改为唯一索引，由数据库保证不重复
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        recreate_index(manager, true).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        recreate_index(manager, false).await
+    }
+}
+
+async fn recreate_index(manager: &SchemaManager<'_>, unique: bool) -> Result<(), DbErr> {
+    if manager.has_index("tokens", INDEX).await? {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name(INDEX)
+                    .table(entity::key::Entity)
+                    .to_owned(),
+            )
+            .await?;
+    }
+    let mut index = Index::create()
+        .name(INDEX)
+        .table(entity::key::Entity)
+        .col(entity::key::Column::Key)
+        .to_owned();
+    if unique {
+        index.unique();
+    }
+    manager.create_index(index).await
+}
@@ -0,0 +1,25 @@
+use sea_orm_migration::prelude::*;
+
+use crate::util::{add_missing_columns, drop_columns};
+
+const COLUMNS: [entity::key::Column; 5] = [
+    entity::key::Column::Name,
+    entity::key::Column::Description,
+    entity::key::Column::Tags,
+    entity::key::Column::CreatedAt,
+    entity::key::Column::UpdatedAt,
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_missing_columns(manager, entity::key::Entity, &COLUMNS).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_columns(manager, entity::key::Entity, &COLUMNS).await
+    }
+}
@@ -0,0 +1,23 @@
+use sea_orm_migration::prelude::*;
+
+use crate::util::{add_missing_columns, drop_columns};
+
+const COLUMNS: [entity::url::Column; 3] = [
+    entity::url::Column::Label,
+    entity::url::Column::Notes,
+    entity::url::Column::Attributes,
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        add_missing_columns(manager, entity::url::Entity, &COLUMNS).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        drop_columns(manager, entity::url::Entity, &COLUMNS).await
+    }
+}
@@ -0,0 +1,54 @@
+use sea_orm::{EntityTrait, Iden, Schema};
+use sea_orm_migration::prelude::*;
+
+/// 为已存在的表补充实体中新增的列，新建的表已由实体直接生成，跳过已有列
+pub async fn add_missing_columns<E>(
+    manager: &SchemaManager<'_>,
+    entity: E,
+    columns: &[E::Column],
+) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    let schema = Schema::new(manager.get_database_backend());
+    for column in columns {
+        if manager
+            .has_column(entity.table_name(), column.to_string())
+            .await?
+        {
+            continue;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(entity)
+                    .add_column(schema.get_column_def::<E>(*column))
+                    .to_owned(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// 回滚时删除实体中的列
+pub async fn drop_columns<E>(
+    manager: &SchemaManager<'_>,
+    entity: E,
+    columns: &[E::Column],
+) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    for column in columns {
+        if !manager
+            .has_column(entity.table_name(), column.to_string())
+            .await?
+        {
+            continue;
+        }
+        manager
+            .alter_table(Table::alter().table(entity).drop_column(*column).to_owned())
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,448 @@
+use chrono::{DateTime, Utc};
+use entity::{
+    key::{self, Mode, PathMode, Rules, Sticky, Strategy},
+    url::{self, Attributes, Schedule},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+/// 导出和导入支持的格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Json,
+    /// 每个 url 一行，key 的设置在每行重复，没有 url 的 key 占一行且 url 为空
+    Csv,
+    Yaml,
+}
+
+impl Format {
+    pub fn from_content_type(content_type: Option<&str>) -> Self {
+        match content_type.map(|value| value.split(';').next().unwrap_or_default().trim()) {
+            Some("text/csv") => Self::Csv,
+            Some("application/yaml" | "application/x-yaml" | "text/yaml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Yaml => "application/yaml",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Yaml => "yaml",
+        }
+    }
+}
+
+/// 一个 key 的完整配置，缺少的设置项使用默认值
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBackup {
+    pub key: String,
+    #[serde(default)]
+    pub strategy: Strategy,
+    #[serde(default)]
+    pub hash_on: Option<String>,
+    #[serde(default)]
+    pub sticky: Sticky,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default)]
+    pub path_mode: PathMode,
+    #[serde(default = "default_redirect_status")]
+    pub redirect_status: i16,
+    #[serde(default)]
+    pub canary_percent: i16,
+    #[serde(default)]
+    pub canary_sticky: bool,
+    #[serde(default)]
+    pub rules: Option<Rules>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub max_hits: Option<i64>,
+    #[serde(default)]
+    pub fallback_url: Option<String>,
+    #[serde(default)]
+    pub disabled: bool,
+    /// 访问密码的 argon2 哈希
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub urls: Vec<UrlBackup>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UrlBackup {
+    pub url: String,
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_pool")]
+    pub pool: String,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub attributes: Option<Attributes>,
+}
+
+fn default_redirect_status() -> i16 {
+    307
+}
+
+fn default_weight() -> i32 {
+    1
+}
+
+fn default_pool() -> String {
+    url::DEFAULT_POOL.to_string()
+}
+
+impl KeyBackup {
+    pub fn new(key: key::Model, urls: Vec<url::Model>) -> Self {
+        Self {
+            key: key.key,
+            strategy: key.strategy,
+            hash_on: key.hash_on,
+            sticky: key.sticky,
+            mode: key.mode,
+            path_mode: key.path_mode,
+            redirect_status: key.redirect_status,
+            canary_percent: key.canary_percent,
+            canary_sticky: key.canary_sticky,
+            rules: key.rules,
+            name: key.name,
+            description: key.description,
+            tags: key.tags.unwrap_or_default().0,
+            expires_at: key.expires_at,
+            max_hits: key.max_hits,
+            fallback_url: key.fallback_url,
+            disabled: key.disabled,
+            password_hash: key.password,
+            urls: urls.into_iter().map(UrlBackup::from).collect(),
+        }
+    }
+}
+
+impl From<url::Model> for UrlBackup {
+    fn from(url: url::Model) -> Self {
+        Self {
+            url: url.url,
+            weight: url.weight,
+            priority: url.priority,
+            pool: url.pool,
+            schedule: url.schedule,
+            disabled: url.disabled,
+            label: url.label,
+            notes: url.notes,
+            attributes: url.attributes,
+        }
+    }
+}
+
+/// CSV 的一行，规则和时间窗口以 JSON 字符串保存
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    key: String,
+    #[serde(default)]
+    strategy: Strategy,
+    hash_on: Option<String>,
+    #[serde(default)]
+    sticky: Sticky,
+    #[serde(default)]
+    mode: Mode,
+    #[serde(default)]
+    path_mode: PathMode,
+    redirect_status: Option<i16>,
+    canary_percent: Option<i16>,
+    canary_sticky: Option<bool>,
+    rules: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    /// JSON 数组
+    tags: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+    max_hits: Option<i64>,
+    fallback_url: Option<String>,
+    /// key 是否禁用，与 url 的 `disabled` 区分
+    key_disabled: Option<bool>,
+    password_hash: Option<String>,
+    url: Option<String>,
+    weight: Option<i32>,
+    priority: Option<i32>,
+    pool: Option<String>,
+    schedule: Option<String>,
+    disabled: Option<bool>,
+    label: Option<String>,
+    notes: Option<String>,
+    /// JSON 对象
+    attributes: Option<String>,
+}
+
+pub fn encode(format: Format, keys: &[KeyBackup]) -> Result<String, AppError> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(keys)?),
+        Format::Yaml => serde_yaml::to_string(keys).map_err(|_| AppError::Invalid),
+        Format::Csv => encode_csv(keys),
+    }
+}
+
+pub fn decode(format: Format, body: &str) -> Result<Vec<KeyBackup>, AppError> {
+    match format {
+        Format::Json => Ok(serde_json::from_str(body)?),
+        Format::Yaml => serde_yaml::from_str(body).map_err(|_| AppError::Invalid),
+        Format::Csv => decode_csv(body),
+    }
+}
+
+fn encode_csv(keys: &[KeyBackup]) -> Result<String, AppError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for key in keys {
+        let rules = key.rules.as_ref().map(serde_json::to_string).transpose()?;
+        let tags = if key.tags.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&key.tags)?)
+        };
+        let row = |url: Option<&UrlBackup>| -> Result<CsvRow, AppError> {
+            Ok(CsvRow {
+                key: key.key.clone(),
+                strategy: key.strategy,
+                hash_on: key.hash_on.clone(),
+                sticky: key.sticky,
+                mode: key.mode,
+                path_mode: key.path_mode,
+                redirect_status: Some(key.redirect_status),
+                canary_percent: Some(key.canary_percent),
+                canary_sticky: Some(key.canary_sticky),
+                rules: rules.clone(),
+                name: key.name.clone(),
+                description: key.description.clone(),
+                tags: tags.clone(),
+                expires_at: key.expires_at,
+                max_hits: key.max_hits,
+                fallback_url: key.fallback_url.clone(),
+                key_disabled: Some(key.disabled),
+                password_hash: key.password_hash.clone(),
+                url: url.map(|url| url.url.clone()),
+                weight: url.map(|url| url.weight),
+                priority: url.map(|url| url.priority),
+                pool: url.map(|url| url.pool.clone()),
+                schedule: url
+                    .and_then(|url| url.schedule.as_ref())
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                disabled: url.map(|url| url.disabled),
+                label: url.and_then(|url| url.label.clone()),
+                notes: url.and_then(|url| url.notes.clone()),
+                attributes: url
+                    .and_then(|url| url.attributes.as_ref())
+                    .map(serde_json::to_string)
+                    .transpose()?,
+            })
+        };
+        if key.urls.is_empty() {
+            writer
+                .serialize(row(None)?)
+                .map_err(|_| AppError::Invalid)?;
+        }
+        for url in &key.urls {
+            writer
+                .serialize(row(Some(url))?)
+                .map_err(|_| AppError::Invalid)?;
+        }
+    }
+    let bytes = writer.into_inner().map_err(|_| AppError::Invalid)?;
+    String::from_utf8(bytes).map_err(|_| AppError::Invalid)
+}
+
+/// 相同 key 的行合并为一个 key，设置取第一行
+fn decode_csv(body: &str) -> Result<Vec<KeyBackup>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+    let mut keys: Vec<KeyBackup> = Vec::new();
+    for row in reader.deserialize::<CsvRow>() {
+        let row = row.map_err(|_| AppError::Invalid)?;
+        let index = match keys.iter().position(|key| key.key == row.key) {
+            Some(index) => index,
+            None => {
+                keys.push(KeyBackup {
+                    key: row.key.clone(),
+                    strategy: row.strategy,
+                    hash_on: row.hash_on,
+                    sticky: row.sticky,
+                    mode: row.mode,
+                    path_mode: row.path_mode,
+                    redirect_status: row.redirect_status.unwrap_or_else(default_redirect_status),
+                    canary_percent: row.canary_percent.unwrap_or_default(),
+                    canary_sticky: row.canary_sticky.unwrap_or_default(),
+                    rules: row.rules.as_deref().map(serde_json::from_str).transpose()?,
+                    name: row.name,
+                    description: row.description,
+                    tags: row
+                        .tags
+                        .as_deref()
+                        .map(serde_json::from_str)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    expires_at: row.expires_at,
+                    max_hits: row.max_hits,
+                    fallback_url: row.fallback_url,
+                    disabled: row.key_disabled.unwrap_or_default(),
+                    password_hash: row.password_hash,
+                    urls: Vec::new(),
+                });
+                keys.len() - 1
+            }
+        };
+        if let Some(url) = row.url {
+            keys[index].urls.push(UrlBackup {
+                url,
+                weight: row.weight.unwrap_or_else(default_weight),
+                priority: row.priority.unwrap_or_default(),
+                pool: row.pool.unwrap_or_else(default_pool),
+                schedule: row
+                    .schedule
+                    .as_deref()
+                    .map(serde_json::from_str)
+                    .transpose()?,
+                disabled: row.disabled.unwrap_or_default(),
+                label: row.label,
+                notes: row.notes,
+                attributes: row
+                    .attributes
+                    .as_deref()
+                    .map(serde_json::from_str)
+                    .transpose()?,
+            });
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::key::{Condition, Field, Op, Rule};
+
+    #[test]
+    fn test_round_trip() {
+        let keys = vec![
+            KeyBackup {
+                key: "docs".to_string(),
+                strategy: Strategy::Weighted,
+                hash_on: None,
+                sticky: Sticky::Cookie,
+                mode: Mode::Redirect,
+                path_mode: PathMode::Append,
+                redirect_status: 302,
+                canary_percent: 5,
+                canary_sticky: true,
+                rules: Some(Rules(vec![Rule {
+                    conditions: vec![Condition {
+                        field: Field::UserAgent,
+                        name: None,
+                        op: Op::Contains,
+                        value: "android".to_string(),
+                    }],
+                    pool: "android".to_string(),
+                    attributes: [("region".to_string(), "hk".to_string())].into(),
+                }])),
+                name: Some("文档镜像".to_string()),
+                description: Some("a, \"quoted\"\nline".to_string()),
+                tags: vec!["docs".to_string(), "cn".to_string()],
+                expires_at: DateTime::from_timestamp(1_800_000_000, 0),
+                max_hits: Some(1000),
+                fallback_url: Some("https://example.com/expired".to_string()),
+                disabled: true,
+                password_hash: Some(
+                    "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA".to_string(),
+                ),
+                urls: vec![
+                    UrlBackup {
+                        url: "https://a.example.com/x,y".to_string(),
+                        weight: 9,
+                        priority: 0,
+                        pool: "default".to_string(),
+                        schedule: Some(Schedule {
+                            start: Some(1),
+                            end: None,
+                            weekly: None,
+                        }),
+                        disabled: false,
+                        label: Some("hk-mirror".to_string()),
+                        notes: None,
+                        attributes: Some(Attributes(
+                            [("region".to_string(), "hk".to_string())].into(),
+                        )),
+                    },
+                    UrlBackup {
+                        url: "https://b.example.com".to_string(),
+                        weight: 1,
+                        priority: 1,
+                        pool: "android".to_string(),
+                        schedule: None,
+                        disabled: true,
+                        label: None,
+                        notes: Some("备用".to_string()),
+                        attributes: None,
+                    },
+                ],
+            },
+            KeyBackup::new(
+                key::Model {
+                    id: 2,
+                    user_id: 1,
+                    key: "empty".to_string(),
+                    strategy: Strategy::Random,
+                    hash_on: Some("header:x-user".to_string()),
+                    sticky: Sticky::Off,
+                    mode: Mode::Proxy,
+                    path_mode: PathMode::Ignore,
+                    redirect_status: 307,
+                    canary_percent: 0,
+                    canary_sticky: false,
+                    rules: None,
+                    name: None,
+                    description: None,
+                    tags: None,
+                    created_at: Default::default(),
+                    updated_at: Default::default(),
+                    expires_at: None,
+                    max_hits: None,
+                    fallback_url: None,
+                    disabled: false,
+                    password: None,
+                },
+                Vec::new(),
+            ),
+        ];
+        for format in [Format::Json, Format::Csv, Format::Yaml] {
+            let encoded = encode(format, &keys).unwrap();
+            assert_eq!(decode(format, &encoded).unwrap(), keys, "{format:?}");
+        }
+    }
+}
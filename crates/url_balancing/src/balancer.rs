@@ -0,0 +1,104 @@
+use entity::url;
+use rand::Rng;
+use ring::digest::{digest, SHA256};
+
+/// 按权重随机选择 url，权重为 0 的 url 不会被选中
+pub fn pick_weighted(urls: &[url::Model]) -> Option<&url::Model> {
+    let total: i64 = urls.iter().map(|url| url.weight.max(0) as i64).sum();
+    if total <= 0 {
+        return None;
+    }
+    let mut point = rand::thread_rng().gen_range(0..total);
+    for url in urls {
+        let weight = url.weight.max(0) as i64;
+        if point < weight {
+            return Some(url);
+        }
+        point -= weight;
+    }
+    None
+}
+
+/// 严格轮询，按 url 排序保证各实例顺序一致
+pub fn pick_round_robin(urls: &[url::Model], counter: u64) -> Option<&url::Model> {
+    let mut urls: Vec<&url::Model> = urls.iter().filter(|url| url.weight > 0).collect();
+    if urls.is_empty() {
+        return None;
+    }
+    urls.sort_by(|a, b| a.url.cmp(&b.url));
+    Some(urls[(counter % urls.len() as u64) as usize])
+}
+
+/// 加权最高随机权重哈希，url 增删时只影响落在该 url 上的请求
+pub fn pick_hashed<'a>(urls: &'a [url::Model], attribute: &str) -> Option<&'a url::Model> {
+    urls.iter()
+        .filter(|url| url.weight > 0)
+        .map(|url| {
+            // 映射到 (0, 1) 区间
+            let point = (hash64(&[attribute, &url.url]) >> 11) as f64 / (1u64 << 53) as f64;
+            let point = point.max(f64::MIN_POSITIVE);
+            (url, -(url.weight as f64) / point.ln())
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(url, _)| url)
+}
+
+/// 是否落入灰度分组，给出客户端标识时同一客户端的结果固定
+pub fn in_canary(percent: i16, key: &str, client: Option<&str>) -> bool {
+    let bucket = match client {
+        Some(client) => hash64(&[key, client]) % 100,
+        None => rand::thread_rng().gen_range(0..100),
+    };
+    bucket < percent.clamp(0, 100) as u64
+}
+
+fn hash64(parts: &[&str]) -> u64 {
+    let hash = digest(&SHA256, parts.join("\n").as_bytes());
+    u64::from_be_bytes(hash.as_ref()[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str, weight: i32) -> url::Model {
+        url::Model {
+            id: 1,
+            url: url.to_string(),
+            weight,
+            priority: 0,
+            pool: url::DEFAULT_POOL.to_string(),
+            schedule: None,
+            disabled: false,
+            label: None,
+            notes: None,
+            attributes: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let urls = vec![url("a", 9), url("b", 1), url("c", 0)];
+        let mut hits = [0; 3];
+        for _ in 0..10000 {
+            let picked = pick_weighted(&urls).unwrap();
+            hits[urls.iter().position(|u| u == picked).unwrap()] += 1;
+        }
+        assert_eq!(hits[2], 0);
+        assert!(hits[0] > 8500 && hits[1] > 500);
+        assert!(pick_weighted(&[url("a", 0)]).is_none());
+    }
+
+    #[test]
+    fn test_pick_hashed() {
+        let urls = vec![url("a", 1), url("b", 1), url("c", 1)];
+        let picked = pick_hashed(&urls, "10.0.0.1").unwrap().url.clone();
+        assert_eq!(pick_hashed(&urls, "10.0.0.1").unwrap().url, picked);
+        // 移除其他 url 不影响已有映射
+        let rest: Vec<url::Model> = urls
+            .into_iter()
+            .filter(|u| u.url == picked || u.url == "a")
+            .collect();
+        assert_eq!(pick_hashed(&rest, "10.0.0.1").unwrap().url, picked);
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::env_or;
+
+/// 被动故障检测配置：窗口内失败次数与失败率均超过阈值时熔断 url
+pub struct CircuitConfig {
+    /// 统计窗口，`CIRCUIT_WINDOW` 秒，同时也是半开状态的持续时间
+    pub window: u64,
+    /// 触发熔断的最少失败次数，`CIRCUIT_MIN_FAILURES`
+    pub min_failures: u64,
+    /// 触发熔断的失败率，`CIRCUIT_FAILURE_RATIO`
+    pub failure_ratio: f64,
+    /// 熔断时长，`CIRCUIT_COOLDOWN` 秒
+    pub cooldown: u64,
+    /// 半开状态下放行的试探请求数，`CIRCUIT_TRIAL_REQUESTS`
+    pub trial_requests: u64,
+}
+
+impl CircuitConfig {
+    pub fn from_env() -> Self {
+        Self {
+            window: env_or("CIRCUIT_WINDOW", 60),
+            min_failures: env_or("CIRCUIT_MIN_FAILURES", 5),
+            failure_ratio: env_or("CIRCUIT_FAILURE_RATIO", 0.5),
+            cooldown: env_or("CIRCUIT_COOLDOWN", 30),
+            trial_requests: env_or("CIRCUIT_TRIAL_REQUESTS", 5),
+        }
+    }
+
+    /// 根据熔断截止时间判断当前状态
+    pub fn state(&self, until: u64, now: u64) -> CircuitState {
+        if now < until {
+            CircuitState::Open
+        } else if now < until + self.window {
+            CircuitState::HalfOpen
+        } else {
+            CircuitState::Closed
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    /// 熔断中，不参与选择
+    Open,
+    /// 熔断结束后的试探期，只放行少量请求，期间再有失败立即重新熔断
+    HalfOpen,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
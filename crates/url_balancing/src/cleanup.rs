@@ -0,0 +1,49 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+
+use crate::{config::env_or, error::AppError, state::AppState};
+
+/// 过期 key 清理配置
+pub struct CleanupConfig {
+    /// 清理间隔，`CLEANUP_INTERVAL` 秒，设为 0 关闭清理
+    pub interval: Duration,
+    /// 过期后保留的时间，`EXPIRED_KEY_RETENTION` 秒，保留期内仍返回备用地址或 410
+    pub retention: Duration,
+}
+
+impl CleanupConfig {
+    pub fn from_env() -> Option<Self> {
+        let interval = env_or("CLEANUP_INTERVAL", 3600);
+        if interval == 0 {
+            return None;
+        }
+        Some(Self {
+            interval: Duration::from_secs(interval),
+            retention: Duration::from_secs(env_or("EXPIRED_KEY_RETENTION", 7 * 24 * 3600)),
+        })
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    let Some(config) = CleanupConfig::from_env() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = cleanup(&state, &config).await {
+                eprintln!("清理过期 key 失败: {err}");
+            }
+        }
+    });
+}
+
+async fn cleanup(state: &AppState, config: &CleanupConfig) -> Result<(), AppError> {
+    let retention = chrono::Duration::from_std(config.retention).map_err(|_| AppError::Invalid)?;
+    for key in state.mdb.get_expired_keys(Utc::now() - retention).await? {
+        state.delete_key(key.user_id, &key.key).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,11 @@
+use std::{env, str::FromStr};
+
+/// 读取并解析环境变量，未设置时使用默认值，格式错误直接退出
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("无效的环境变量 {name}")),
+        Err(_) => default,
+    }
+}
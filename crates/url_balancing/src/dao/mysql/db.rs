@@ -1,7 +1,7 @@
 use super::*;
 use crate::error::AppError;
 use entity::{key, url};
-use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveValue::Set, SqlErr, TransactionTrait, TryIntoModel};
 
 pub struct DbSrv {
     pub db: DbConn,
@@ -18,7 +18,11 @@ impl DbSrv {
             ..Default::default()
         }
         .insert(&self.db)
-        .await?;
+        .await
+        .map_err(|err| match err.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => AppError::KeyExists,
+            _ => err.into(),
+        })?;
         Ok(key)
     }
 
@@ -38,29 +42,60 @@ impl DbSrv {
         Ok(keys)
     }
 
-    pub async fn add_url(&self, key: &str, url: &str) -> Result<(), AppError> {
+    /// 在 `before` 之前过期的 key
+    pub async fn get_expired_keys(&self, before: DateTimeUtc) -> Result<Vec<key::Model>, AppError> {
+        Ok(key::Entity::find()
+            .filter(key::Column::ExpiresAt.lt(before))
+            .all(&self.db)
+            .await?)
+    }
+
+    pub async fn get_all_keys(&self) -> Result<Vec<key::Model>, AppError> {
+        Ok(key::Entity::find().all(&self.db).await?)
+    }
+
+    pub async fn add_url(
+        &self,
+        key: &str,
+        mut url: url::ActiveModel,
+    ) -> Result<url::Model, AppError> {
         let key = self.check_key(key).await?;
         if let Some(key) = key {
-            url::ActiveModel {
-                id: Set(key.id),
-                url: Set(url.to_string()),
-                ..Default::default()
-            }
-            .insert(&self.db)
-            .await?;
-            return Ok(());
+            url.id = Set(key.id);
+            return Ok(url.insert(&self.db).await?);
         }
         Err(AppError::KeyNotFound)
     }
 
-    pub async fn get_urls(&self, key: &str) -> Result<Vec<String>, AppError> {
+    /// 一条语句批量插入 url
+    pub async fn add_urls(
+        &self,
+        key: &str,
+        mut urls: Vec<url::ActiveModel>,
+    ) -> Result<Vec<url::Model>, AppError> {
+        let key = self.check_key(key).await?.ok_or(AppError::KeyNotFound)?;
+        for url in &mut urls {
+            url.id = Set(key.id);
+        }
+        let models = urls
+            .iter()
+            .cloned()
+            .map(TryIntoModel::try_into_model)
+            .collect::<Result<Vec<_>, _>>()?;
+        if !urls.is_empty() {
+            url::Entity::insert_many(urls).exec(&self.db).await?;
+        }
+        Ok(models)
+    }
+
+    pub async fn get_urls(&self, key: &str) -> Result<Vec<url::Model>, AppError> {
         let key = self.check_key(key).await?;
         if let Some(key) = key {
             let urls = url::Entity::find()
                 .filter(url::Column::Id.eq(key.id))
                 .all(&self.db)
                 .await?;
-            return Ok(urls.iter().map(|url| url.url.clone()).collect());
+            return Ok(urls);
         }
         Err(AppError::KeyNotFound)
     }
@@ -80,13 +115,88 @@ impl DbSrv {
         Err(AppError::KeyNotFound)
     }
 
-    pub async fn delete_key(&self, key: &str) -> Result<(), AppError> {
-        let key = self.check_key(key).await?;
-        if let Some(key) = key {
-            key.delete(&self.db).await?;
-            return Ok(());
+    pub async fn set_url_disabled(
+        &self,
+        key: &str,
+        url: &str,
+        disabled: bool,
+    ) -> Result<url::Model, AppError> {
+        let url = self.get_url(key, url).await?.ok_or(AppError::Invalid)?;
+        let mut url: url::ActiveModel = url.into();
+        url.disabled = Set(disabled);
+        Ok(url.update(&self.db).await?)
+    }
+
+    /// 修改 url 的属性，`url` 为修改前的地址，地址变化时在事务中替换整行
+    pub async fn update_url(
+        &self,
+        url: &str,
+        active: url::ActiveModel,
+    ) -> Result<url::Model, AppError> {
+        if active.url.as_ref() == url {
+            return Ok(active.update(&self.db).await?);
         }
-        Err(AppError::KeyNotFound)
+        let txn = self.db.begin().await?;
+        url::Entity::delete_by_id((*active.id.as_ref(), url.to_string()))
+            .exec(&txn)
+            .await?;
+        let url = active.insert(&txn).await?;
+        txn.commit().await?;
+        Ok(url)
+    }
+
+    /// 在事务中用 `urls` 替换 key 的全部 url
+    pub async fn replace_urls(
+        &self,
+        key: &str,
+        mut urls: Vec<url::ActiveModel>,
+    ) -> Result<Vec<url::Model>, AppError> {
+        let key = self.check_key(key).await?.ok_or(AppError::KeyNotFound)?;
+        for url in &mut urls {
+            url.id = Set(key.id);
+        }
+        let txn = self.db.begin().await?;
+        url::Entity::delete_many()
+            .filter(url::Column::Id.eq(key.id))
+            .exec(&txn)
+            .await?;
+        if !urls.is_empty() {
+            url::Entity::insert_many(urls).exec(&txn).await?;
+        }
+        let urls = url::Entity::find()
+            .filter(url::Column::Id.eq(key.id))
+            .all(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(urls)
+    }
+
+    pub async fn get_url(&self, key: &str, url: &str) -> Result<Option<url::Model>, AppError> {
+        let key = self.check_key(key).await?.ok_or(AppError::KeyNotFound)?;
+        Ok(url::Entity::find_by_id((key.id, url.to_string()))
+            .one(&self.db)
+            .await?)
+    }
+
+    /// 在事务中删除 key 及其全部 url，返回被删除的 url
+    pub async fn delete_key(&self, key: &str) -> Result<Vec<url::Model>, AppError> {
+        let key = self.check_key(key).await?.ok_or(AppError::KeyNotFound)?;
+        let txn = self.db.begin().await?;
+        let urls = url::Entity::find()
+            .filter(url::Column::Id.eq(key.id))
+            .all(&txn)
+            .await?;
+        url::Entity::delete_many()
+            .filter(url::Column::Id.eq(key.id))
+            .exec(&txn)
+            .await?;
+        key.delete(&txn).await?;
+        txn.commit().await?;
+        Ok(urls)
+    }
+
+    pub async fn update_key(&self, key: key::ActiveModel) -> Result<key::Model, AppError> {
+        Ok(key.update(&self.db).await?)
     }
 
     pub async fn get_key(&self, key: &str) -> Result<Option<key::Model>, AppError> {
@@ -1,6 +1,12 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::HashMap,
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use super::*;
+use crate::circuit::{self, CircuitConfig};
+use entity::{key, url};
 
 pub struct RdSrv {
     pub db: redis::Client,
@@ -10,6 +16,129 @@ const REDIS_PREFIX: &str = "URL_BALANCING";
 const REDIS_KEY: &str = "KEY";
 const REDIS_LIST_PREFIX: &str = "LIST";
 const REDIS_CSRF: &str = "CSRF";
+const REDIS_URL_PREFIX: &str = "URL";
+const REDIS_LOADED_PREFIX: &str = "LOADED";
+const REDIS_GEN_PREFIX: &str = "GEN";
+const REDIS_CONF_PREFIX: &str = "CONF";
+const REDIS_RR_PREFIX: &str = "RR";
+const REDIS_SWRR_PREFIX: &str = "SWRR";
+const REDIS_HEALTH_PREFIX: &str = "HEALTH";
+const REDIS_CIRCUIT_PREFIX: &str = "CB";
+const REDIS_REPORTER_PREFIX: &str = "CBR";
+const REDIS_TRIAL_PREFIX: &str = "CBT";
+const REDIS_EJECT_PREFIX: &str = "EJECT";
+const REDIS_HITS_PREFIX: &str = "HITS";
+const REDIS_ATTEMPT_PREFIX: &str = "ATTEMPT";
+
+// 平滑加权轮询：每次给所有 url 加上各自权重，选出当前权重最大者并减去总权重
+static SMOOTH_WEIGHTED: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+local total, best, best_weight = 0, nil, nil
+local current = {}
+for i = 1, #ARGV, 2 do
+    local url, weight = ARGV[i], tonumber(ARGV[i + 1])
+    local value = tonumber(redis.call('HGET', KEYS[1], url) or '0') + weight
+    current[url] = value
+    total = total + weight
+    if best_weight == nil or value > best_weight then
+        best, best_weight = url, value
+    end
+end
+if best == nil then
+    return false
+end
+current[best] = current[best] - total
+redis.call('DEL', KEYS[1])
+for url, value in pairs(current) do
+    redis.call('HSET', KEYS[1], url, value)
+end
+return best
+",
+    )
+});
+
+// 记录一次请求，计数在窗口结束后过期
+static RECORD_REQUEST: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+redis.call('HINCRBY', KEYS[1], 'req', 1)
+if redis.call('TTL', KEYS[1]) < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+",
+    )
+});
+
+// 记录一次失败，达到阈值或处于半开状态时熔断，返回是否熔断
+static REPORT_FAILURE: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+local now = tonumber(ARGV[2])
+local deadline = tonumber(redis.call('HGET', KEYS[2], ARGV[1]) or '0')
+if deadline > now then
+    return 1
+end
+-- 客户端上报时同一窗口内每个地址只计一次
+if ARGV[7] ~= '' then
+    if redis.call('SADD', KEYS[3], ARGV[7]) == 0 then
+        return 0
+    end
+    if redis.call('TTL', KEYS[3]) < 0 then
+        redis.call('EXPIRE', KEYS[3], ARGV[3])
+    end
+end
+local fail = redis.call('HINCRBY', KEYS[1], 'fail', 1)
+if redis.call('TTL', KEYS[1]) < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[3])
+end
+local req = tonumber(redis.call('HGET', KEYS[1], 'req') or '0')
+local half_open = deadline > 0 and now < deadline + tonumber(ARGV[3])
+local tripped = fail >= tonumber(ARGV[4]) and fail >= tonumber(ARGV[5]) * math.max(req, fail)
+if half_open or tripped then
+    redis.call('HSET', KEYS[2], ARGV[1], now + tonumber(ARGV[6]))
+    redis.call('DEL', KEYS[1])
+    return 1
+end
+return 0
+",
+    )
+});
+
+// 消耗一次跳转次数，已达上限时不计数，返回是否成功
+static CONSUME_HIT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+local hits = tonumber(redis.call('GET', KEYS[1]) or '0')
+if hits >= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('INCR', KEYS[1])
+return 1
+",
+    )
+});
+
+// 从 MySQL 重建 url 缓存，期间有写入（版本号变化）时放弃，避免旧快照覆盖新写入的 url
+// ARGV 依次为读取 MySQL 前的版本号，以及每个 url 的地址、是否启用、属性
+static LOAD_URLS: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+if (redis.call('GET', KEYS[4]) or '0') ~= ARGV[1] then
+    return 0
+end
+redis.call('DEL', KEYS[1], KEYS[2])
+for i = 2, #ARGV, 3 do
+    if ARGV[i + 1] == '1' then
+        redis.call('SADD', KEYS[1], ARGV[i])
+    end
+    redis.call('HSET', KEYS[2], ARGV[i], ARGV[i + 2])
+end
+redis.call('SET', KEYS[3], 1)
+return 1
+",
+    )
+});
 
 macro_rules! concat_string {
     // 匹配多个参数
@@ -31,6 +160,43 @@ macro_rules! concat_string {
     }};
 }
 
+fn pipe_add_url(pipe: &mut redis::Pipeline, key: &str, url: &url::Model) -> Result<(), AppError> {
+    let list_key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
+    let url_key = concat_string!(REDIS_PREFIX, REDIS_URL_PREFIX, key);
+    if url.disabled {
+        pipe.srem(list_key, &url.url).ignore();
+    } else {
+        pipe.sadd(list_key, &url.url).ignore();
+    }
+    pipe.hset(url_key, &url.url, serde_json::to_string(url)?)
+        .ignore();
+    Ok(())
+}
+
+/// 每次写入 url 缓存都增加版本号，见 [`LOAD_URLS`]
+fn pipe_bump(pipe: &mut redis::Pipeline, key: &str) {
+    pipe.incr(concat_string!(REDIS_PREFIX, REDIS_GEN_PREFIX, key), 1)
+        .ignore();
+}
+
+fn pipe_delete_url(pipe: &mut redis::Pipeline, key: &str, url: &str) {
+    let list_key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
+    let url_key = concat_string!(REDIS_PREFIX, REDIS_URL_PREFIX, key);
+    let health_key = concat_string!(REDIS_PREFIX, REDIS_HEALTH_PREFIX, key);
+    let circuit_key = concat_string!(REDIS_PREFIX, REDIS_CIRCUIT_PREFIX, key, url);
+    let eject_key = concat_string!(REDIS_PREFIX, REDIS_EJECT_PREFIX, key);
+    pipe.srem(list_key, url)
+        .ignore()
+        .hdel(url_key, url)
+        .ignore()
+        .hdel(health_key, url)
+        .ignore()
+        .del(circuit_key)
+        .ignore()
+        .hdel(eject_key, url)
+        .ignore();
+}
+
 impl RdSrv {
     pub fn new(db: redis::Client) -> Self {
         Self { db }
@@ -52,6 +218,55 @@ impl RdSrv {
         Ok(con.sadd(key_set, key).await?)
     }
 
+    /// 只从用户的 key 集合中移除，用于撤销写入失败的 key
+    pub async fn remove_user_key(&self, uid: i64, key: &str) -> Result<(), AppError> {
+        let user_key = concat_string!(REDIS_PREFIX, REDIS_KEY, uid.to_string().as_str());
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.srem(user_key, key).await?)
+    }
+
+    /// 删除 key 相关的全部缓存和状态，并从用户的 key 集合中移除以释放配额
+    pub async fn delete_key(
+        &self,
+        uid: i64,
+        key: &str,
+        urls: &[url::Model],
+    ) -> Result<(), AppError> {
+        let key_set = concat_string!(REDIS_PREFIX, REDIS_KEY);
+        let user_key = concat_string!(&key_set, uid.to_string().as_str());
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .srem(key_set, key)
+            .ignore()
+            .srem(user_key, key)
+            .ignore();
+        for prefix in [
+            REDIS_LIST_PREFIX,
+            REDIS_URL_PREFIX,
+            REDIS_CONF_PREFIX,
+            REDIS_RR_PREFIX,
+            REDIS_SWRR_PREFIX,
+            REDIS_HEALTH_PREFIX,
+            REDIS_EJECT_PREFIX,
+            REDIS_HITS_PREFIX,
+            REDIS_LOADED_PREFIX,
+            REDIS_GEN_PREFIX,
+        ] {
+            pipe.del(concat_string!(REDIS_PREFIX, prefix, key)).ignore();
+        }
+        for url in urls {
+            pipe.del(concat_string!(
+                REDIS_PREFIX,
+                REDIS_CIRCUIT_PREFIX,
+                key,
+                &url.url
+            ))
+            .ignore();
+        }
+        Ok(pipe.query_async(&mut con).await?)
+    }
+
     pub async fn check_key(&self, uid: Option<i64>, key: &str) -> Result<bool, AppError> {
         let key_set = concat_string!(REDIS_PREFIX, REDIS_KEY);
         let mut con = self.db.get_multiplexed_tokio_connection().await?;
@@ -62,29 +277,303 @@ impl RdSrv {
         Ok(con.sismember(key_set, key).await?)
     }
 
-    pub async fn get_url(&self, key: &str) -> Result<Option<String>, AppError> {
-        let key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
+    /// 获取参与负载均衡的 url 及其属性，任一 url 缺少属性时视为缓存未同步，返回空
+    /// 选择集合中的 url，缓存没有从 MySQL 完整加载过时返回 `None`
+    pub async fn get_pool(&self, key: &str) -> Result<Option<Vec<url::Model>>, AppError> {
+        let loaded_key = concat_string!(REDIS_PREFIX, REDIS_LOADED_PREFIX, key);
+        let list_key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
+        let url_key = concat_string!(REDIS_PREFIX, REDIS_URL_PREFIX, key);
         let mut con = self.db.get_multiplexed_tokio_connection().await?;
-        Ok(con.srandmember(key).await?)
+        let (loaded, members, metas): (bool, Vec<String>, HashMap<String, String>) = redis::pipe()
+            .exists(loaded_key)
+            .smembers(list_key)
+            .hgetall(url_key)
+            .query_async(&mut con)
+            .await?;
+        if !loaded {
+            return Ok(None);
+        }
+        // 旧版本写入的属性缺少字段时视为未命中，由调用方从 MySQL 重新加载
+        Ok(members
+            .iter()
+            .filter_map(|member| metas.get(member))
+            .map(|meta| serde_json::from_str(meta))
+            .collect::<Result<_, _>>()
+            .ok())
+    }
+
+    /// 读取 MySQL 之前先取版本号，重建缓存时用来判断期间是否有写入
+    pub async fn url_generation(&self, key: &str) -> Result<u64, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_GEN_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let generation: Option<u64> = con.get(key).await?;
+        Ok(generation.unwrap_or_default())
     }
 
-    pub async fn add_url(&self, key: &str, url: &str) -> Result<(), AppError> {
-        let key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
+    /// 用 MySQL 中的 url 重建缓存，`generation` 之后有写入时不重建，下次读取再加载
+    pub async fn load_urls(
+        &self,
+        key: &str,
+        generation: u64,
+        urls: &[url::Model],
+    ) -> Result<(), AppError> {
+        let mut script = LOAD_URLS.prepare_invoke();
+        script
+            .key(concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key))
+            .key(concat_string!(REDIS_PREFIX, REDIS_URL_PREFIX, key))
+            .key(concat_string!(REDIS_PREFIX, REDIS_LOADED_PREFIX, key))
+            .key(concat_string!(REDIS_PREFIX, REDIS_GEN_PREFIX, key))
+            .arg(generation);
+        for url in urls {
+            script
+                .arg(&url.url)
+                .arg(if url.disabled { 0 } else { 1 })
+                .arg(serde_json::to_string(url)?);
+        }
         let mut con = self.db.get_multiplexed_tokio_connection().await?;
-        Ok(con.sadd(key, url).await?)
+        let _: bool = script.invoke_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 写入 url 属性，停用的 url 不加入选择集合
+    pub async fn add_url(&self, key: &str, url: &url::Model) -> Result<(), AppError> {
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        pipe_add_url(&mut pipe, key, url)?;
+        pipe_bump(&mut pipe, key);
+        Ok(pipe.query_async(&mut con).await?)
     }
 
     pub async fn delete_url(&self, key: &str, url: &str) -> Result<(), AppError> {
-        let key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
         let mut con = self.db.get_multiplexed_tokio_connection().await?;
-        Ok(con.srem(key, url).await?)
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        pipe_delete_url(&mut pipe, key, url);
+        pipe_bump(&mut pipe, key);
+        Ok(pipe.query_async(&mut con).await?)
     }
 
-    pub async fn get_urls(&self, key: &str) -> Result<Vec<String>, AppError> {
-        let key = concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key);
+    /// 在同一个事务中删除 `removed` 并写入 `urls`，`reset` 时 `urls` 是完整的 url 池，
+    /// 先清空选择集合和属性
+    pub async fn replace_urls(
+        &self,
+        key: &str,
+        removed: &[String],
+        urls: &[url::Model],
+        reset: bool,
+    ) -> Result<(), AppError> {
         let mut con = self.db.get_multiplexed_tokio_connection().await?;
-        Ok(con.smembers(key).await?)
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for url in removed {
+            pipe_delete_url(&mut pipe, key, url);
+        }
+        if reset {
+            pipe.del(concat_string!(REDIS_PREFIX, REDIS_LIST_PREFIX, key))
+                .ignore()
+                .del(concat_string!(REDIS_PREFIX, REDIS_URL_PREFIX, key))
+                .ignore()
+                .set(concat_string!(REDIS_PREFIX, REDIS_LOADED_PREFIX, key), 1)
+                .ignore();
+        }
+        for url in urls {
+            pipe_add_url(&mut pipe, key, url)?;
+        }
+        pipe_bump(&mut pipe, key);
+        Ok(pipe.query_async(&mut con).await?)
+    }
+
+    pub async fn record_request(&self, key: &str, url: &str, window: u64) -> Result<(), AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_CIRCUIT_PREFIX, key, url);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(RECORD_REQUEST
+            .key(key)
+            .arg(window)
+            .invoke_async(&mut con)
+            .await?)
+    }
+
+    /// `reporter` 为上报的客户端地址，服务端自己发现的失败为 `None`，每次都计数
+    pub async fn report_failure(
+        &self,
+        key: &str,
+        url: &str,
+        reporter: Option<&str>,
+        config: &CircuitConfig,
+    ) -> Result<bool, AppError> {
+        let circuit_key = concat_string!(REDIS_PREFIX, REDIS_CIRCUIT_PREFIX, key, url);
+        let eject_key = concat_string!(REDIS_PREFIX, REDIS_EJECT_PREFIX, key);
+        let reporter_key = concat_string!(REDIS_PREFIX, REDIS_REPORTER_PREFIX, key, url);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(REPORT_FAILURE
+            .key(circuit_key)
+            .key(eject_key)
+            .key(reporter_key)
+            .arg(url)
+            .arg(circuit::now())
+            .arg(config.window)
+            .arg(config.min_failures)
+            .arg(config.failure_ratio)
+            .arg(config.cooldown)
+            .arg(reporter.unwrap_or_default())
+            .invoke_async(&mut con)
+            .await?)
+    }
+
+    /// 半开状态下领取一次试探机会，返回本次熔断后已领取的次数
+    pub async fn take_trial(
+        &self,
+        key: &str,
+        url: &str,
+        until: u64,
+        window: u64,
+    ) -> Result<u64, AppError> {
+        let key = concat_string!(
+            REDIS_PREFIX,
+            REDIS_TRIAL_PREFIX,
+            key,
+            url,
+            until.to_string().as_str()
+        );
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let (trials,): (u64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("EX")
+            .arg(window)
+            .arg("NX")
+            .ignore()
+            .incr(&key, 1)
+            .query_async(&mut con)
+            .await?;
+        Ok(trials)
     }
+
+    /// 返回 url 的熔断截止时间
+    pub async fn get_ejections(&self, key: &str) -> Result<HashMap<String, u64>, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_EJECT_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.hgetall(key).await?)
+    }
+
+    pub async fn close_circuit(&self, key: &str, url: &str) -> Result<(), AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_EJECT_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.hdel(key, url).await?)
+    }
+
+    pub async fn set_health(&self, key: &str, url: &str, healthy: bool) -> Result<(), AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_HEALTH_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.hset(key, url, healthy).await?)
+    }
+
+    /// 未检查过的 url 不在结果中
+    pub async fn get_health(&self, key: &str) -> Result<HashMap<String, bool>, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_HEALTH_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.hgetall(key).await?)
+    }
+
+    /// 包括停用的 url，选择集合中有 url 缺少属性时同样视为未命中
+    /// 包括停用的全部 url，缓存没有从 MySQL 完整加载过时返回 `None`
+    pub async fn get_urls(&self, key: &str) -> Result<Option<Vec<url::Model>>, AppError> {
+        let loaded_key = concat_string!(REDIS_PREFIX, REDIS_LOADED_PREFIX, key);
+        let url_key = concat_string!(REDIS_PREFIX, REDIS_URL_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let (loaded, metas): (bool, HashMap<String, String>) = redis::pipe()
+            .exists(loaded_key)
+            .hgetall(url_key)
+            .query_async(&mut con)
+            .await?;
+        if !loaded {
+            return Ok(None);
+        }
+        Ok(metas
+            .values()
+            .map(|meta| serde_json::from_str(meta))
+            .collect::<Result<_, _>>()
+            .ok())
+    }
+
+    pub async fn get_key_conf(&self, key: &str) -> Result<Option<key::Model>, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_CONF_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let conf: Option<String> = con.get(key).await?;
+        Ok(conf.and_then(|conf| serde_json::from_str(&conf).ok()))
+    }
+
+    pub async fn set_key_conf(&self, conf: &key::Model) -> Result<(), AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_CONF_PREFIX, &conf.key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.set(key, serde_json::to_string(conf)?).await?)
+    }
+
+    pub async fn consume_hit(&self, key: &str, max_hits: i64) -> Result<bool, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_HITS_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(CONSUME_HIT
+            .key(key)
+            .arg(max_hits)
+            .invoke_async(&mut con)
+            .await?)
+    }
+
+    pub async fn reset_hits(&self, key: &str) -> Result<(), AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_HITS_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.del(key).await?)
+    }
+
+    /// 记录一次密码尝试并返回窗口内的次数，计数从第一次尝试起 `window` 秒后过期
+    pub async fn add_attempt(&self, key: &str, client: &str, window: u64) -> Result<u64, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_ATTEMPT_PREFIX, key, client);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        let (attempts,): (u64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("EX")
+            .arg(window)
+            .arg("NX")
+            .ignore()
+            .incr(&key, 1)
+            .query_async(&mut con)
+            .await?;
+        Ok(attempts)
+    }
+
+    /// 密码正确后清空计数
+    pub async fn clear_attempts(&self, key: &str, client: &str) -> Result<(), AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_ATTEMPT_PREFIX, key, client);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.del(key).await?)
+    }
+
+    pub async fn next_round_robin(&self, key: &str) -> Result<u64, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_RR_PREFIX, key);
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(con.incr(key, 1).await?)
+    }
+
+    pub async fn next_smooth_weighted(
+        &self,
+        key: &str,
+        urls: &[url::Model],
+    ) -> Result<Option<String>, AppError> {
+        let key = concat_string!(REDIS_PREFIX, REDIS_SWRR_PREFIX, key);
+        let mut invocation = SMOOTH_WEIGHTED.key(key);
+        for url in urls.iter().filter(|url| url.weight > 0) {
+            invocation.arg(&url.url).arg(url.weight);
+        }
+        let mut con = self.db.get_multiplexed_tokio_connection().await?;
+        Ok(invocation.invoke_async(&mut con).await?)
+    }
+
     pub async fn set_csrf(&self, csrf: &str) -> Result<(), AppError> {
         let key = concat_string!(REDIS_PREFIX, REDIS_CSRF);
         let current_time = SystemTime::now()
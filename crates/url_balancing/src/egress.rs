@@ -0,0 +1,109 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use thiserror::Error;
+use url::{Host, Url};
+
+use crate::config::env_or;
+
+/// 是否允许访问内网地址，`ALLOW_PRIVATE_BACKENDS`，只应在所有用户都可信时打开
+static ALLOW_PRIVATE: LazyLock<bool> = LazyLock::new(|| env_or("ALLOW_PRIVATE_BACKENDS", false));
+
+/// 解析器拒绝了域名的全部地址
+#[derive(Debug, Error)]
+#[error("{0} 没有允许访问的地址")]
+pub struct Blocked(String);
+
+/// 排除回环、私有、链路本地（含云厂商元数据地址）、共享地址等不应由服务端访问的地址
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // fc00::/7 唯一本地地址
+                    || first & 0xfe00 == 0xfc00
+                    // fe80::/10 链路本地地址
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+fn is_allowed(ip: IpAddr) -> bool {
+    *ALLOW_PRIVATE || is_public(ip)
+}
+
+/// 连接前检查 IP 形式的地址，这类地址不经过解析器
+pub fn is_allowed_url(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Domain(_)) => true,
+        Some(Host::Ipv4(ip)) => is_allowed(ip.into()),
+        Some(Host::Ipv6(ip)) => is_allowed(ip.into()),
+        None => false,
+    }
+}
+
+/// 丢弃内网地址的 DNS 解析器，用于代理和健康检查的出站请求
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_allowed(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(Blocked(name.as_str().to_string()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["1.1.1.1", "8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
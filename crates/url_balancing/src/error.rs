@@ -1,6 +1,8 @@
+use std::{env, fs, sync::LazyLock};
+
 use axum::{
     http::StatusCode,
-    response::{IntoResponse, Response},
+    response::{Html, IntoResponse, Response},
     Json,
 };
 use oauth2::{basic::BasicErrorResponseType, RequestTokenError, StandardErrorResponse};
@@ -8,6 +10,14 @@ use serde::Serialize;
 use strum_macros::EnumDiscriminants;
 use thiserror::Error;
 
+/// `NOT_FOUND_PAGE` 指定的 HTML 文件，用于替代纯文本的 404，读取失败时仍返回纯文本
+static NOT_FOUND_PAGE: LazyLock<Option<String>> = LazyLock::new(|| {
+    let path = env::var("NOT_FOUND_PAGE").ok()?;
+    fs::read_to_string(&path)
+        .inspect_err(|err| eprintln!("读取 404 页面 {path} 失败: {err}"))
+        .ok()
+});
+
 #[repr(i8)]
 #[derive(Error, Debug, EnumDiscriminants)]
 #[strum_discriminants(name(AppErrorKind))]
@@ -42,6 +52,18 @@ pub enum AppError {
     Invalid,
     #[error("数量达到上限")]
     Limit,
+    #[error("JSON错误: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("502 Bad Gateway")]
+    BadGateway,
+    #[error("Key 已存在")]
+    KeyExists,
+    #[error("410 Gone")]
+    Gone,
+    #[error("429 Too Many Requests")]
+    TooManyRequests,
+    #[error("413 Payload Too Large")]
+    PayloadTooLarge,
     #[error("未知错误")]
     Unknown,
 }
@@ -61,7 +83,18 @@ pub struct ErrorResponse {
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         match &self {
-            AppError::HTTPNotFound => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+            AppError::HTTPNotFound => match NOT_FOUND_PAGE.as_deref() {
+                Some(page) => (StatusCode::NOT_FOUND, Html(page)).into_response(),
+                None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+            },
+            AppError::BadGateway => (StatusCode::BAD_GATEWAY, "502 Bad Gateway").into_response(),
+            AppError::Gone => (StatusCode::GONE, "410 Gone").into_response(),
+            AppError::TooManyRequests => {
+                (StatusCode::TOO_MANY_REQUESTS, "429 Too Many Requests").into_response()
+            }
+            AppError::PayloadTooLarge => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "413 Payload Too Large").into_response()
+            }
             _ => (
                 StatusCode::OK,
                 Json(ErrorResponse {
@@ -1,28 +1,313 @@
-use crate::{error::AppError, oauth::LinuxDoUser, state::AppState, token};
+use crate::{
+    backup::{self, KeyBackup, UrlBackup},
+    config::env_or,
+    error::AppError,
+    import,
+    oauth::LinuxDoUser,
+    password::{self, Access},
+    proxy,
+    request::RequestInfo,
+    rules, schedule,
+    state::AppState,
+    sticky, target, template, token,
+};
 use axum::{
-    extract::{Extension, Json, Path},
-    http::StatusCode,
-    response::Redirect,
+    body::Body,
+    extract::{Extension, Json, Path, Query, Request},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION, SET_COOKIE},
+        HeaderMap, StatusCode,
+    },
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use entity::{
+    key::{self, Mode, PathMode, Rules, Sticky, Strategy, Tags},
+    url,
 };
+use sea_orm::ActiveValue::{Set, Unchanged};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, LazyLock},
+};
 
 const LIMITATION: i16 = 100;
+const REDIRECT_STATUS: [i16; 5] = [301, 302, 303, 307, 308];
+
+/// 永久跳转的缓存时间，`REDIRECT_CACHE_MAX_AGE` 秒
+static REDIRECT_MAX_AGE: LazyLock<u64> = LazyLock::new(|| env_or("REDIRECT_CACHE_MAX_AGE", 3600));
+
+fn redirect(status: i16, location: &str) -> Result<Response, AppError> {
+    let status = StatusCode::from_u16(status as u16).map_err(|_| AppError::Invalid)?;
+    let mut response = Response::builder()
+        .status(status)
+        .header(LOCATION, location);
+    // 永久跳转会被浏览器长期缓存，限制缓存时间，避免客户端一直固定在同一个后端
+    if matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+    ) {
+        let cache_control = format!("private, max-age={}", *REDIRECT_MAX_AGE);
+        response = response.header(CACHE_CONTROL, cache_control);
+    }
+    response.body(Body::empty()).map_err(|_| AppError::Invalid)
+}
+
+/// key 被禁用或没有可用的 url 时，依次使用 key 的备用地址和全局备用地址，都没有则返回 404
+fn fallback(state: &AppState, conf: &key::Model) -> Result<Response, AppError> {
+    match conf
+        .fallback_url
+        .as_deref()
+        .or(state.fallback_url.as_deref())
+    {
+        Some(url) => redirect(307, url),
+        None => Err(AppError::HTTPNotFound),
+    }
+}
+
+/// 选出 url 并生成跳转，主机部分的占位符没有取值时换一个 url，没有可用 url 时返回 404
+async fn redirect_to(
+    state: &AppState,
+    conf: &key::Model,
+    req: &RequestInfo,
+) -> Result<(String, Response), AppError> {
+    let mut skipped = Vec::new();
+    while let Some(url) = state.get_url(conf, req, &skipped).await? {
+        let target = match target::build(&url, conf, req) {
+            Err(AppError::HTTPNotFound) => {
+                skipped.push(url);
+                continue;
+            }
+            target => target?,
+        };
+        if !state.consume_hit(conf).await? {
+            return Err(AppError::Gone);
+        }
+        let response = redirect(conf.redirect_status, target.as_str())?;
+        return Ok((url, response));
+    }
+    Err(AppError::HTTPNotFound)
+}
+
+/// key 已过期或跳转次数用尽时跳转到 key 的备用地址，没有则返回 410
+fn expired(conf: &key::Model) -> Result<Response, AppError> {
+    match &conf.fallback_url {
+        Some(fallback_url) => redirect(307, fallback_url),
+        None => Err(AppError::Gone),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BalancePath {
+    key: String,
+}
 
 pub async fn url_balancing(
-    Path(key): Path<String>,
+    Path(BalancePath { key }): Path<BalancePath>,
     Extension(state): Extension<Arc<AppState>>,
-) -> Result<Redirect, AppError> {
-    let backend_url = state.get_url(&key).await?;
-    match backend_url {
-        Some(url) => Ok(Redirect::temporary(&url)),
-        None => Err(AppError::HTTPNotFound),
+    mut req: RequestInfo,
+    request: Request,
+) -> Result<Response, AppError> {
+    let conf = state.get_key(&key).await?.ok_or(AppError::HTTPNotFound)?;
+    if conf.disabled {
+        return fallback(&state, &conf);
+    }
+    let (request, auth_cookie) = match &conf.password {
+        Some(hash) => match password::authorize(&state, &conf, hash, &mut req, request).await? {
+            Access::Granted(request, cookie) => (request, cookie),
+            Access::Respond(response) => return Ok(response),
+        },
+        None => (request, None),
+    };
+    if state.is_expired(&conf) {
+        return expired(&conf);
+    }
+    let selected = match conf.mode {
+        Mode::Redirect => redirect_to(&state, &conf, &req).await,
+        Mode::Proxy => proxy::forward(&state, &conf, &req, request).await,
+    };
+    // 分组为空或全部不健康
+    let (url, mut response) = match selected {
+        Err(AppError::HTTPNotFound) => return fallback(&state, &conf),
+        Err(AppError::Gone) => return expired(&conf),
+        selected => selected?,
+    };
+    if conf.sticky == Sticky::Cookie
+        && req.cookie(&sticky::cookie_name(&key)) != Some(sticky::url_digest(&url))
+    {
+        let cookie = sticky::cookie(&key, &url);
+        response
+            .headers_mut()
+            .append(SET_COOKIE, cookie.parse().map_err(|_| AppError::Invalid)?);
     }
+    if let Some(cookie) = auth_cookie {
+        response
+            .headers_mut()
+            .append(SET_COOKIE, cookie.parse().map_err(|_| AppError::Invalid)?);
+    }
+    Ok(response)
 }
 
 #[derive(Deserialize)]
 pub struct AddUrlRequest {
     url: String,
+    #[serde(default = "default_weight")]
+    weight: i32,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default = "default_pool")]
+    pool: String,
+    schedule: Option<url::Schedule>,
+    #[serde(default)]
+    disabled: bool,
+    label: Option<String>,
+    notes: Option<String>,
+    attributes: Option<url::Attributes>,
+}
+
+impl AddUrlRequest {
+    fn into_active_model(self) -> Result<url::ActiveModel, AppError> {
+        if self.weight < 0 || !is_valid_pool(&self.pool) {
+            return Err(AppError::Invalid);
+        }
+        template::validate(&self.url)?;
+        if let Some(schedule) = &self.schedule {
+            schedule::validate(schedule)?;
+        }
+        let label = validate_label(self.label)?;
+        let notes = validate_notes(self.notes)?;
+        let attributes = validate_attributes(self.attributes)?;
+        Ok(url::ActiveModel {
+            url: Set(self.url),
+            weight: Set(self.weight),
+            priority: Set(self.priority),
+            pool: Set(self.pool),
+            schedule: Set(self
+                .schedule
+                .filter(|schedule| *schedule != Default::default())),
+            disabled: Set(self.disabled),
+            label: Set(label),
+            notes: Set(notes),
+            attributes: Set(attributes),
+            ..Default::default()
+        })
+    }
+}
+
+impl From<UrlBackup> for AddUrlRequest {
+    fn from(backup: UrlBackup) -> Self {
+        Self {
+            url: backup.url,
+            weight: backup.weight,
+            priority: backup.priority,
+            pool: backup.pool,
+            schedule: backup.schedule,
+            disabled: backup.disabled,
+            label: backup.label,
+            notes: backup.notes,
+            attributes: backup.attributes,
+        }
+    }
+}
+
+/// 空字符串视为清除
+fn validate_label(label: Option<String>) -> Result<Option<String>, AppError> {
+    let label = label.map(|label| label.trim().to_string());
+    if label
+        .as_ref()
+        .is_some_and(|label| label.chars().count() > 64)
+    {
+        return Err(AppError::Invalid);
+    }
+    Ok(label.filter(|label| !label.is_empty()))
+}
+
+fn validate_notes(notes: Option<String>) -> Result<Option<String>, AppError> {
+    if notes
+        .as_ref()
+        .is_some_and(|notes| notes.chars().count() > 1024)
+    {
+        return Err(AppError::Invalid);
+    }
+    Ok(notes.filter(|notes| !notes.trim().is_empty()))
+}
+
+/// 属性名规则与分组名相同，空对象视为清除
+fn validate_attributes(
+    attributes: Option<url::Attributes>,
+) -> Result<Option<url::Attributes>, AppError> {
+    let Some(attributes) = attributes.filter(|attributes| !attributes.0.is_empty()) else {
+        return Ok(None);
+    };
+    if attributes.0.len() > 16
+        || attributes
+            .0
+            .iter()
+            .any(|(name, value)| !is_valid_pool(name) || value.chars().count() > 128)
+    {
+        return Err(AppError::Invalid);
+    }
+    Ok(Some(attributes))
+}
+
+fn default_weight() -> i32 {
+    1
+}
+
+fn default_pool() -> String {
+    url::DEFAULT_POOL.to_string()
+}
+
+fn is_valid_pool(pool: &str) -> bool {
+    (1..=32).contains(&pool.len())
+        && pool
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Unknown,
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+pub struct UrlResponse {
+    url: String,
+    weight: i32,
+    priority: i32,
+    pool: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schedule: Option<url::Schedule>,
+    disabled: bool,
+    health: Health,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    metadata: Option<UrlMetadata>,
+}
+
+/// `view=full` 时附加的 url 描述信息
+#[derive(Serialize)]
+pub struct UrlMetadata {
+    label: Option<String>,
+    notes: Option<String>,
+    attributes: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlView {
+    #[default]
+    Basic,
+    Full,
+}
+
+#[derive(Deserialize)]
+pub struct UrlsQuery {
+    #[serde(default)]
+    view: UrlView,
 }
 
 #[derive(Serialize)]
@@ -48,7 +333,7 @@ pub async fn add_url(
         return Err(AppError::Invalid);
     }
 
-    state.add_url(&key, &payload.url).await?;
+    state.add_url(&key, payload.into_active_model()?).await?;
 
     Ok((
         StatusCode::OK,
@@ -59,20 +344,72 @@ pub async fn add_url(
     ))
 }
 
+#[derive(Deserialize)]
+pub struct CreateKeyRequest {
+    /// 自定义 key，不填则随机生成
+    key: Option<String>,
+}
+
 pub async fn create_key(
     Extension(user): Extension<LinuxDoUser>,
     Extension(state): Extension<Arc<AppState>>,
+    payload: Option<Json<CreateKeyRequest>>,
 ) -> Result<(StatusCode, Json<CommonResponse<String>>), AppError> {
-    let key = token::new_token();
-    if state.check_key(Some(user.id), &key).await? {
+    let key = match payload.and_then(|Json(payload)| payload.key) {
+        Some(key) => {
+            if !token::is_valid_custom(&key) {
+                return Err(AppError::Invalid);
+            }
+            if state.check_key(None, &key).await? {
+                return Err(AppError::KeyExists);
+            }
+            state.add_key(user.id, &key, LIMITATION).await?;
+            key
+        }
+        None => new_random_key(&state, user.id).await?,
+    };
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: Some(key),
+        }),
+    ))
+}
+
+/// 生成随机 key，与已有 key 冲突或是保留字时重新生成
+async fn new_random_key(state: &AppState, uid: i64) -> Result<String, AppError> {
+    for _ in 0..=state.token.retries {
+        let key = token::new_token(&state.token);
+        if !token::is_valid_custom(&key) || state.check_key(None, &key).await? {
+            continue;
+        }
+        match state.add_key(uid, &key, LIMITATION).await {
+            Ok(()) => return Ok(key),
+            Err(AppError::KeyExists) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(AppError::KeyExists)
+}
+
+/// 删除 key 及其全部 url，释放占用的配额
+pub async fn delete_key(
+    Path(key): Path<String>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<(StatusCode, Json<CommonResponse<()>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
         return Err(AppError::Invalid);
     }
-    state.add_key(user.id, &key, LIMITATION).await?;
+
+    state.delete_key(user.id, &key).await?;
+
     Ok((
         StatusCode::OK,
         Json(CommonResponse {
             code: 0,
-            data: Some(key),
+            data: None,
         }),
     ))
 }
@@ -98,15 +435,292 @@ pub async fn delete_url(
     ))
 }
 
+#[derive(Deserialize)]
+pub struct UpdateUrlRequest {
+    url: String,
+    /// 新的地址，不填则保持不变
+    new_url: Option<String>,
+    weight: Option<i32>,
+    priority: Option<i32>,
+    pool: Option<String>,
+    /// 空对象表示清除时间窗口
+    schedule: Option<url::Schedule>,
+    disabled: Option<bool>,
+    /// 以下三项为空时清除
+    label: Option<String>,
+    notes: Option<String>,
+    attributes: Option<url::Attributes>,
+}
+
+pub async fn update_url(
+    Path(key): Path<String>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<UpdateUrlRequest>,
+) -> Result<(StatusCode, Json<CommonResponse<()>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
+        return Err(AppError::Invalid);
+    }
+    let url = state
+        .find_url(&key, &payload.url)
+        .await?
+        .ok_or(AppError::Invalid)?;
+
+    let mut active: url::ActiveModel = url.into();
+    if let Some(new_url) = payload.new_url {
+        template::validate(&new_url)?;
+        active.url = Set(new_url);
+    }
+    if let Some(weight) = payload.weight {
+        if weight < 0 {
+            return Err(AppError::Invalid);
+        }
+        active.weight = Set(weight);
+    }
+    if let Some(priority) = payload.priority {
+        active.priority = Set(priority);
+    }
+    if let Some(pool) = payload.pool {
+        if !is_valid_pool(&pool) {
+            return Err(AppError::Invalid);
+        }
+        active.pool = Set(pool);
+    }
+    if let Some(schedule) = payload.schedule {
+        if schedule == Default::default() {
+            active.schedule = Set(None);
+        } else {
+            schedule::validate(&schedule)?;
+            active.schedule = Set(Some(schedule));
+        }
+    }
+    if let Some(disabled) = payload.disabled {
+        active.disabled = Set(disabled);
+    }
+    if payload.label.is_some() {
+        active.label = Set(validate_label(payload.label)?);
+    }
+    if payload.notes.is_some() {
+        active.notes = Set(validate_notes(payload.notes)?);
+    }
+    if payload.attributes.is_some() {
+        active.attributes = Set(validate_attributes(payload.attributes)?);
+    }
+    state.update_url(&key, &payload.url, active).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: None,
+        }),
+    ))
+}
+
+/// 用请求中的 url 整体替换 key 的 url 池，用于部署时一次性下发
+pub async fn replace_urls(
+    Path(key): Path<String>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<Vec<AddUrlRequest>>,
+) -> Result<(StatusCode, Json<CommonResponse<()>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
+        return Err(AppError::Invalid);
+    }
+
+    let mut seen = HashSet::with_capacity(payload.len());
+    if !payload.iter().all(|url| seen.insert(url.url.as_str())) {
+        return Err(AppError::Invalid);
+    }
+    let urls = payload
+        .into_iter()
+        .map(AddUrlRequest::into_active_model)
+        .collect::<Result<_, _>>()?;
+    state.replace_urls(&key, urls).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: None,
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    format: Option<import::Format>,
+    /// 只校验不写入
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportResponse {
+    imported: usize,
+    dry_run: bool,
+    errors: Vec<import::LineError>,
+}
+
+/// 批量导入 url，任意一行校验失败时不写入，返回每行的错误
+pub async fn import_urls(
+    Path(key): Path<String>,
+    Query(query): Query<ImportQuery>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<CommonResponse<ImportResponse>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
+        return Err(AppError::Invalid);
+    }
+
+    let format = query.format.unwrap_or_else(|| {
+        import::Format::from_content_type(
+            headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok()),
+        )
+    });
+    let mut seen: HashSet<String> = state
+        .get_urls(&key)
+        .await?
+        .into_iter()
+        .map(|url| url.url)
+        .collect();
+    let mut urls = Vec::new();
+    let mut errors = Vec::new();
+    for (line, row) in import::parse::<AddUrlRequest>(format, &body) {
+        let result = row.and_then(|row| {
+            if !seen.insert(row.url.clone()) {
+                return Err("url 已存在".to_string());
+            }
+            row.into_active_model().map_err(|err| err.to_string())
+        });
+        match result {
+            Ok(url) => urls.push(url),
+            Err(error) => errors.push(import::LineError { line, error }),
+        }
+    }
+
+    let imported = if errors.is_empty() { urls.len() } else { 0 };
+    if errors.is_empty() && !query.dry_run {
+        state.add_urls(&key, urls).await?;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: Some(ImportResponse {
+                imported,
+                dry_run: query.dry_run,
+                errors,
+            }),
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct DisableUrlRequest {
+    url: String,
+    disabled: bool,
+}
+
+/// 停用或启用 url，故障处理时临时摘除而不丢失配置
+pub async fn set_url_disabled(
+    Path(key): Path<String>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<DisableUrlRequest>,
+) -> Result<(StatusCode, Json<CommonResponse<()>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
+        return Err(AppError::Invalid);
+    }
+
+    state
+        .set_url_disabled(&key, &payload.url, payload.disabled)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: None,
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    url: String,
+}
+
+#[derive(Serialize)]
+pub struct ReportResponse {
+    ejected: bool,
+}
+
+/// 客户端上报跳转后的 url 无法访问，可配合 `navigator.sendBeacon` 使用
+pub async fn report_failure(
+    Path(key): Path<String>,
+    Extension(state): Extension<Arc<AppState>>,
+    req: RequestInfo,
+    Json(payload): Json<ReportRequest>,
+) -> Result<(StatusCode, Json<CommonResponse<ReportResponse>>), AppError> {
+    // 没有客户端地址时无法去重，拒绝上报
+    let reporter = req.ip.ok_or(AppError::Invalid)?;
+    let ejected = state
+        .report_failure(&key, &payload.url, Some(reporter))
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: Some(ReportResponse { ejected }),
+        }),
+    ))
+}
+
+/// 只有 key 的所有者可以查看后端地址，避免绕过访问密码
 pub async fn get_urls(
     Path(key): Path<String>,
+    Query(query): Query<UrlsQuery>,
+    Extension(user): Extension<LinuxDoUser>,
     Extension(state): Extension<Arc<AppState>>,
-) -> Result<(StatusCode, Json<CommonResponse<Vec<String>>>), AppError> {
-    if !state.check_key(None, &key).await.unwrap() {
+) -> Result<(StatusCode, Json<CommonResponse<Vec<UrlResponse>>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
         return Err(AppError::Invalid);
     }
 
-    let urls = state.get_urls(&key).await?;
+    let health = state.get_health(&key).await?;
+    let urls = state
+        .get_urls(&key)
+        .await?
+        .into_iter()
+        .map(|url| UrlResponse {
+            metadata: match query.view {
+                UrlView::Basic => None,
+                UrlView::Full => Some(UrlMetadata {
+                    label: url.label,
+                    notes: url.notes,
+                    attributes: url.attributes.unwrap_or_default().0,
+                }),
+            },
+            health: match health.get(&url.url) {
+                Some(true) => Health::Up,
+                Some(false) => Health::Down,
+                None => Health::Unknown,
+            },
+            url: url.url,
+            weight: url.weight,
+            priority: url.priority,
+            pool: url.pool,
+            schedule: url.schedule,
+            disabled: url.disabled,
+        })
+        .collect();
 
     Ok((
         StatusCode::OK,
@@ -117,11 +731,49 @@ pub async fn get_urls(
     ))
 }
 
+#[derive(Deserialize)]
+pub struct KeysQuery {
+    /// 只返回带有该标签的 key
+    tag: Option<String>,
+    /// 在 key、名称和描述中搜索，不区分大小写
+    q: Option<String>,
+}
+
+impl KeysQuery {
+    fn matches(&self, key: &key::Model) -> bool {
+        let tag = self.tag.as_deref().map(|tag| tag.trim().to_lowercase());
+        let tagged =
+            tag.is_none_or(|tag| key.tags.as_ref().is_some_and(|tags| tags.0.contains(&tag)));
+        let q = self.q.as_deref().map(str::to_lowercase);
+        let found = q.is_none_or(|q| {
+            [Some(&key.key), key.name.as_ref(), key.description.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|text| text.to_lowercase().contains(&q))
+        });
+        tagged && found
+    }
+}
+
 pub async fn get_keys(
+    Query(query): Query<KeysQuery>,
     Extension(user): Extension<LinuxDoUser>,
     Extension(state): Extension<Arc<AppState>>,
-) -> Result<(StatusCode, Json<CommonResponse<Vec<String>>>), AppError> {
-    let tokens = state.get_user_keys(user.id).await?;
+) -> Result<(StatusCode, Json<CommonResponse<Vec<KeyResponse>>>), AppError> {
+    let mut keys = Vec::new();
+    for key in state.get_user_keys(user.id).await? {
+        if let Some(conf) = state.get_key(&key).await? {
+            if query.matches(&conf) {
+                keys.push(conf);
+            }
+        }
+    }
+    keys.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    let tokens: Vec<KeyResponse> = keys.into_iter().map(KeyResponse::from).collect();
 
     Ok((
         StatusCode::OK,
@@ -131,3 +783,440 @@ pub async fn get_keys(
         }),
     ))
 }
+
+#[derive(Serialize)]
+pub struct KeyResponse {
+    key: String,
+    strategy: Strategy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash_on: Option<String>,
+    sticky: Sticky,
+    mode: Mode,
+    path_mode: PathMode,
+    redirect_status: i16,
+    canary_percent: i16,
+    canary_sticky: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rules: Option<Rules>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    tags: Vec<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_hits: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fallback_url: Option<String>,
+    disabled: bool,
+    /// 是否设置了访问密码，不返回哈希
+    protected: bool,
+}
+
+impl From<key::Model> for KeyResponse {
+    fn from(key: key::Model) -> Self {
+        Self {
+            key: key.key,
+            strategy: key.strategy,
+            hash_on: key.hash_on,
+            sticky: key.sticky,
+            mode: key.mode,
+            path_mode: key.path_mode,
+            redirect_status: key.redirect_status,
+            canary_percent: key.canary_percent,
+            canary_sticky: key.canary_sticky,
+            rules: key.rules,
+            name: key.name,
+            description: key.description,
+            tags: key.tags.unwrap_or_default().0,
+            created_at: key.created_at,
+            updated_at: key.updated_at,
+            expires_at: key.expires_at,
+            max_hits: key.max_hits,
+            fallback_url: key.fallback_url,
+            disabled: key.disabled,
+            protected: key.password.is_some(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateKeyRequest {
+    strategy: Option<Strategy>,
+    /// 空字符串表示恢复默认的客户端地址
+    hash_on: Option<String>,
+    sticky: Option<Sticky>,
+    mode: Option<Mode>,
+    path_mode: Option<PathMode>,
+    redirect_status: Option<i16>,
+    canary_percent: Option<i16>,
+    canary_sticky: Option<bool>,
+    /// 按顺序匹配的路由规则，空数组表示清除
+    rules: Option<Rules>,
+    /// 空字符串表示清除
+    name: Option<String>,
+    /// 空字符串表示清除
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    /// RFC 3339 格式的过期时间，空字符串表示永不过期
+    expires_at: Option<String>,
+    /// 0 表示不限次数
+    max_hits: Option<i64>,
+    /// 空字符串表示清除，清除后过期时返回 410，没有可用 url 时使用全局备用地址
+    fallback_url: Option<String>,
+    disabled: Option<bool>,
+    /// 访问密码，4 到 128 个字符，空字符串表示取消密码
+    password: Option<String>,
+}
+
+impl UpdateKeyRequest {
+    /// 校验并写入修改的设置项
+    async fn apply(self, active: &mut key::ActiveModel) -> Result<(), AppError> {
+        if let Some(strategy) = self.strategy {
+            active.strategy = Set(strategy);
+        }
+        if let Some(sticky) = self.sticky {
+            active.sticky = Set(sticky);
+        }
+        if let Some(mode) = self.mode {
+            active.mode = Set(mode);
+        }
+        if let Some(path_mode) = self.path_mode {
+            active.path_mode = Set(path_mode);
+        }
+        if let Some(redirect_status) = self.redirect_status {
+            if !REDIRECT_STATUS.contains(&redirect_status) {
+                return Err(AppError::Invalid);
+            }
+            active.redirect_status = Set(redirect_status);
+        }
+        if let Some(canary_percent) = self.canary_percent {
+            if !(0..=100).contains(&canary_percent) {
+                return Err(AppError::Invalid);
+            }
+            active.canary_percent = Set(canary_percent);
+        }
+        if let Some(canary_sticky) = self.canary_sticky {
+            active.canary_sticky = Set(canary_sticky);
+        }
+        if let Some(rules) = self.rules {
+            if rules.0.is_empty() {
+                active.rules = Set(None);
+            } else {
+                rules::validate(&rules, is_valid_pool)?;
+                active.rules = Set(Some(rules));
+            }
+        }
+        if let Some(name) = self.name {
+            let name = name.trim();
+            if name.chars().count() > 64 {
+                return Err(AppError::Invalid);
+            }
+            active.name = Set(Some(name.to_string()).filter(|name| !name.is_empty()));
+        }
+        if let Some(description) = self.description {
+            if description.chars().count() > 1024 {
+                return Err(AppError::Invalid);
+            }
+            active.description = Set(Some(description).filter(|d| !d.trim().is_empty()));
+        }
+        if let Some(tags) = self.tags {
+            let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+            for tag in tags {
+                let tag = tag.trim().to_lowercase();
+                if tag.is_empty() || tag.chars().count() > 32 {
+                    return Err(AppError::Invalid);
+                }
+                if !normalized.contains(&tag) {
+                    normalized.push(tag);
+                }
+            }
+            if normalized.len() > 16 {
+                return Err(AppError::Invalid);
+            }
+            active.tags = Set(Some(Tags(normalized)).filter(|tags| !tags.0.is_empty()));
+        }
+        if let Some(hash_on) = self.hash_on {
+            if hash_on.is_empty() {
+                active.hash_on = Set(None);
+            } else if RequestInfo::is_valid_attribute(&hash_on) {
+                active.hash_on = Set(Some(hash_on));
+            } else {
+                return Err(AppError::Invalid);
+            }
+        }
+        if let Some(disabled) = self.disabled {
+            active.disabled = Set(disabled);
+        }
+        if let Some(password) = self.password {
+            if password.is_empty() {
+                active.password = Set(None);
+            } else if (4..=128).contains(&password.chars().count()) {
+                active.password = Set(Some(password::hash(password).await?));
+            } else {
+                return Err(AppError::Invalid);
+            }
+        }
+        if let Some(expires_at) = self.expires_at {
+            if expires_at.is_empty() {
+                active.expires_at = Set(None);
+            } else {
+                let expires_at =
+                    DateTime::parse_from_rfc3339(&expires_at).map_err(|_| AppError::Invalid)?;
+                active.expires_at = Set(Some(expires_at.to_utc()));
+            }
+        }
+        if let Some(max_hits) = self.max_hits {
+            if max_hits < 0 {
+                return Err(AppError::Invalid);
+            }
+            // 值不变时保持未修改，避免重置已用的跳转次数
+            let max_hits = Some(max_hits).filter(|&max_hits| max_hits > 0);
+            if active.max_hits != Unchanged(max_hits) {
+                active.max_hits = Set(max_hits);
+            }
+        }
+        if let Some(fallback_url) = self.fallback_url {
+            if fallback_url.is_empty() {
+                active.fallback_url = Set(None);
+            } else {
+                // 备用地址不经过负载均衡，不支持模板
+                if template::is_template(&fallback_url) {
+                    return Err(AppError::Invalid);
+                }
+                // 保存规范化后的地址，非 ASCII 字符编码后才能写入 Location
+                let fallback_url = template::sample(&fallback_url)?.to_string();
+                active.fallback_url = Set(Some(fallback_url));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&KeyBackup> for UpdateKeyRequest {
+    fn from(backup: &KeyBackup) -> Self {
+        Self {
+            strategy: Some(backup.strategy),
+            hash_on: Some(backup.hash_on.clone().unwrap_or_default()),
+            sticky: Some(backup.sticky),
+            mode: Some(backup.mode),
+            path_mode: Some(backup.path_mode),
+            redirect_status: Some(backup.redirect_status),
+            canary_percent: Some(backup.canary_percent),
+            canary_sticky: Some(backup.canary_sticky),
+            rules: Some(backup.rules.clone().unwrap_or_default()),
+            name: Some(backup.name.clone().unwrap_or_default()),
+            description: Some(backup.description.clone().unwrap_or_default()),
+            tags: Some(backup.tags.clone()),
+            expires_at: Some(
+                backup
+                    .expires_at
+                    .map(|expires_at| expires_at.to_rfc3339())
+                    .unwrap_or_default(),
+            ),
+            max_hits: Some(backup.max_hits.unwrap_or_default()),
+            fallback_url: Some(backup.fallback_url.clone().unwrap_or_default()),
+            disabled: Some(backup.disabled),
+            // 备份中只有哈希，导入时直接写入
+            password: None,
+        }
+    }
+}
+
+pub async fn get_key(
+    Path(key): Path<String>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<(StatusCode, Json<CommonResponse<KeyResponse>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
+        return Err(AppError::Invalid);
+    }
+    let conf = state.get_key(&key).await?.ok_or(AppError::KeyNotFound)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: Some(conf.into()),
+        }),
+    ))
+}
+
+pub async fn update_key(
+    Path(key): Path<String>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+    Json(payload): Json<UpdateKeyRequest>,
+) -> Result<(StatusCode, Json<CommonResponse<KeyResponse>>), AppError> {
+    if !state.check_key(Some(user.id), &key).await? {
+        return Err(AppError::Invalid);
+    }
+    let conf = state.get_key(&key).await?.ok_or(AppError::KeyNotFound)?;
+
+    let mut active: key::ActiveModel = conf.into();
+    payload.apply(&mut active).await?;
+    let conf = state.update_key(active).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: Some(conf.into()),
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: backup::Format,
+}
+
+/// 导出当前用户全部 key 的设置和 url
+pub async fn export_keys(
+    Query(query): Query<ExportQuery>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let mut keys = Vec::new();
+    for key in state.get_user_keys(user.id).await? {
+        if let Some(conf) = state.get_key(&key).await? {
+            let urls = state.get_urls(&key).await?;
+            keys.push(KeyBackup::new(conf, urls));
+        }
+    }
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let format = query.format;
+    let disposition = format!(
+        "attachment; filename=\"url_balancing.{}\"",
+        format.extension()
+    );
+    Response::builder()
+        .header(CONTENT_TYPE, format.content_type())
+        .header(CONTENT_DISPOSITION, disposition)
+        .body(Body::from(backup::encode(format, &keys)?))
+        .map_err(|_| AppError::Invalid)
+}
+
+#[derive(Deserialize)]
+pub struct ImportKeysQuery {
+    format: Option<backup::Format>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+pub struct KeyImportError {
+    key: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportKeysResponse {
+    imported: usize,
+    dry_run: bool,
+    errors: Vec<KeyImportError>,
+}
+
+/// 导入 `export_keys` 导出的配置，已有的 key 覆盖设置并替换 url 池，不存在的 key 会被创建
+pub async fn import_keys(
+    Query(query): Query<ImportKeysQuery>,
+    Extension(user): Extension<LinuxDoUser>,
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<CommonResponse<ImportKeysResponse>>), AppError> {
+    let format = query.format.unwrap_or_else(|| {
+        backup::Format::from_content_type(
+            headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok()),
+        )
+    });
+    let keys = backup::decode(format, &body)?;
+
+    // 先校验全部 key，任意一个失败时不写入
+    let mut errors = Vec::new();
+    let mut seen = HashSet::with_capacity(keys.len());
+    for backup in &keys {
+        if let Err(err) = check_import(&state, user.id, backup, &mut seen).await {
+            errors.push(KeyImportError {
+                key: backup.key.clone(),
+                error: err.to_string(),
+            });
+        }
+    }
+
+    let imported = if errors.is_empty() { keys.len() } else { 0 };
+    if errors.is_empty() && !query.dry_run {
+        for backup in keys {
+            if !state.check_key(Some(user.id), &backup.key).await? {
+                state.add_key(user.id, &backup.key, LIMITATION).await?;
+            }
+            let conf = state
+                .get_key(&backup.key)
+                .await?
+                .ok_or(AppError::KeyNotFound)?;
+            let mut active: key::ActiveModel = conf.into();
+            UpdateKeyRequest::from(&backup).apply(&mut active).await?;
+            active.password = Set(backup.password_hash.clone());
+            state.update_key(active).await?;
+            let urls = backup
+                .urls
+                .into_iter()
+                .map(|url| AddUrlRequest::from(url).into_active_model())
+                .collect::<Result<_, _>>()?;
+            state.replace_urls(&backup.key, urls).await?;
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(CommonResponse {
+            code: 0,
+            data: Some(ImportKeysResponse {
+                imported,
+                dry_run: query.dry_run,
+                errors,
+            }),
+        }),
+    ))
+}
+
+async fn check_import<'a>(
+    state: &AppState,
+    uid: i64,
+    backup: &'a KeyBackup,
+    seen: &mut HashSet<&'a str>,
+) -> Result<(), AppError> {
+    if !seen.insert(&backup.key) || !token::is_valid_custom(&backup.key) {
+        return Err(AppError::Invalid);
+    }
+    // 已被其他用户占用的 key 不能导入
+    if !state.check_key(Some(uid), &backup.key).await? && state.check_key(None, &backup.key).await?
+    {
+        return Err(AppError::Invalid);
+    }
+    UpdateKeyRequest::from(backup)
+        .apply(&mut Default::default())
+        .await?;
+    if backup
+        .password_hash
+        .as_deref()
+        .is_some_and(|hash| !password::is_valid_hash(hash))
+    {
+        return Err(AppError::Invalid);
+    }
+    let mut urls = HashSet::with_capacity(backup.urls.len());
+    for url in &backup.urls {
+        if !urls.insert(url.url.as_str()) {
+            return Err(AppError::Invalid);
+        }
+        AddUrlRequest::from(url.clone()).into_active_model()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::{env, sync::Arc, time::Duration};
+
+use reqwest::{redirect::Policy, Client, StatusCode};
+use tokio::task::JoinSet;
+
+use crate::{
+    config::env_or,
+    egress::{self, PublicResolver},
+    error::AppError,
+    state::AppState,
+    template,
+};
+
+/// 主动健康检查配置，均通过环境变量设置
+///
+/// 探测由服务端发出，与代理模式一样不会访问内网地址
+pub struct HealthConfig {
+    /// 检查间隔，`HEALTH_CHECK_INTERVAL` 秒，默认为 0 即关闭检查
+    pub interval: Duration,
+    /// 探测路径，`HEALTH_CHECK_PATH`，为空时直接探测 url 本身
+    pub path: String,
+    /// 期望的状态码，`HEALTH_CHECK_STATUS`，未设置时 2xx 和 3xx 均视为健康
+    pub status: Option<StatusCode>,
+    /// 单次探测超时，`HEALTH_CHECK_TIMEOUT` 秒
+    pub timeout: Duration,
+}
+
+impl HealthConfig {
+    pub fn from_env() -> Option<Self> {
+        let interval = env_or("HEALTH_CHECK_INTERVAL", 0);
+        if interval == 0 {
+            return None;
+        }
+        Some(Self {
+            interval: Duration::from_secs(interval),
+            path: env::var("HEALTH_CHECK_PATH").unwrap_or_default(),
+            status: env::var("HEALTH_CHECK_STATUS")
+                .ok()
+                .map(|status| status.parse().expect("无效的环境变量 HEALTH_CHECK_STATUS")),
+            timeout: Duration::from_secs(env_or("HEALTH_CHECK_TIMEOUT", 5)),
+        })
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    let Some(config) = HealthConfig::from_env() else {
+        return;
+    };
+    let config = Arc::new(config);
+    let client = Client::builder()
+        .redirect(Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .timeout(config.timeout)
+        .build()
+        .expect("创建健康检查客户端失败");
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = check_all(&state, &client, &config).await {
+                eprintln!("健康检查失败: {err}");
+            }
+        }
+    });
+}
+
+async fn check_all(
+    state: &AppState,
+    client: &Client,
+    config: &Arc<HealthConfig>,
+) -> Result<(), AppError> {
+    for key in state.mdb.get_all_keys().await? {
+        // 单个 key 失败（如检查期间被删除）不影响其余 key
+        if let Err(err) = check_key(state, client, config, &key.key).await {
+            eprintln!("健康检查 {} 失败: {err}", key.key);
+        }
+    }
+    Ok(())
+}
+
+async fn check_key(
+    state: &AppState,
+    client: &Client,
+    config: &Arc<HealthConfig>,
+    key: &str,
+) -> Result<(), AppError> {
+    let urls = state.get_urls(key).await?;
+    let mut probes = JoinSet::new();
+    // 模板需要请求信息才能渲染，无法主动探测
+    for url in urls
+        .into_iter()
+        .filter(|url| !template::is_template(&url.url))
+    {
+        let client = client.clone();
+        let config = config.clone();
+        probes.spawn(async move {
+            let healthy = probe(&client, &config, &url.url).await;
+            (url.url, healthy)
+        });
+    }
+    while let Some(result) = probes.join_next().await {
+        // 不允许访问的地址不记录状态，避免 url 列表泄露内网探测结果
+        let Ok((url, Some(healthy))) = result else {
+            continue;
+        };
+        state.rdb.set_health(key, &url, healthy).await?;
+    }
+    Ok(())
+}
+
+/// 目标地址不允许访问时返回 `None`
+async fn probe(client: &Client, config: &HealthConfig, url: &str) -> Option<bool> {
+    let Ok(mut target) = url::Url::parse(url) else {
+        return Some(false);
+    };
+    if !config.path.is_empty() {
+        match target.join(&config.path) {
+            Ok(joined) => target = joined,
+            Err(_) => return Some(false),
+        }
+    }
+    if !egress::is_allowed_url(&target) {
+        return None;
+    }
+    let healthy = match client.get(target).send().await {
+        Ok(response) => match config.status {
+            Some(status) => response.status() == status,
+            None => response.status().is_success() || response.status().is_redirection(),
+        },
+        Err(err) if err.is_connect() && is_blocked(&err) => return None,
+        Err(_) => false,
+    };
+    Some(healthy)
+}
+
+/// 解析器拒绝了全部地址
+fn is_blocked(err: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if err.is::<egress::Blocked>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
@@ -0,0 +1,148 @@
+use csv::{ReaderBuilder, StringRecord, Trim};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// 批量导入支持的格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Csv,
+    Json,
+    /// 每行一个 url，忽略空行和 `#` 开头的注释
+    Text,
+}
+
+impl Format {
+    /// 未指定格式时按 Content-Type 推断
+    pub fn from_content_type(content_type: Option<&str>) -> Self {
+        match content_type.map(|value| value.split(';').next().unwrap_or_default().trim()) {
+            Some("text/csv") => Self::Csv,
+            Some("application/json") => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// 某一行解析或校验失败的原因，`line` 从 1 开始，JSON 格式为数组下标加 1
+#[derive(Debug, Serialize)]
+pub struct LineError {
+    pub line: usize,
+    pub error: String,
+}
+
+/// 逐行解析导入内容，返回每行的行号和解析结果
+pub fn parse<T: DeserializeOwned>(format: Format, body: &str) -> Vec<(usize, Result<T, String>)> {
+    match format {
+        Format::Json => match serde_json::from_str::<Vec<Value>>(body) {
+            Ok(items) => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    (
+                        i + 1,
+                        serde_json::from_value(item).map_err(|e| e.to_string()),
+                    )
+                })
+                .collect(),
+            Err(err) => vec![(err.line(), Err(err.to_string()))],
+        },
+        Format::Text => body
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+            .map(|(i, line)| {
+                let item = serde_json::json!({ "url": line.trim() });
+                (
+                    i + 1,
+                    serde_json::from_value(item).map_err(|e| e.to_string()),
+                )
+            })
+            .collect(),
+        Format::Csv => parse_csv(body),
+    }
+}
+
+/// 首个非注释行以 `url` 开头时视为表头，否则按 `url,weight,priority,pool,disabled` 的顺序解析
+fn parse_csv<T: DeserializeOwned>(body: &str) -> Vec<(usize, Result<T, String>)> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(body.as_bytes());
+    let mut headers = StringRecord::from(vec!["url", "weight", "priority", "pool", "disabled"]);
+    let mut results = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let line = err.position().map_or(i + 1, |pos| pos.line() as usize);
+                results.push((line, Err(err.to_string())));
+                continue;
+            }
+        };
+        let line = record.position().map_or(i + 1, |pos| pos.line() as usize);
+        // 不使用 csv 自带的注释处理，以免行号跳过注释行
+        if record.iter().all(str::is_empty) || record.get(0).is_some_and(|v| v.starts_with('#')) {
+            continue;
+        }
+        if results.is_empty() && record.get(0) == Some("url") {
+            headers = record;
+            continue;
+        }
+        // 空字段视为未填写，使用默认值
+        let (names, values): (StringRecord, StringRecord) = headers
+            .iter()
+            .zip(record.iter())
+            .filter(|(_, value)| !value.is_empty())
+            .unzip();
+        results.push((
+            line,
+            values.deserialize(Some(&names)).map_err(|e| e.to_string()),
+        ));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row {
+        url: String,
+        #[serde(default)]
+        weight: i32,
+        pool: Option<String>,
+    }
+
+    #[test]
+    fn test_parse() {
+        let csv = "url,pool,weight\nhttps://a.com,,5\n# 注释\nhttps://b.com,hk,x\n";
+        let rows = parse::<Row>(Format::Csv, csv);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0],
+            (
+                2,
+                Ok(Row {
+                    url: "https://a.com".to_string(),
+                    weight: 5,
+                    pool: None,
+                })
+            )
+        );
+        assert_eq!(rows[1].0, 4);
+        assert!(rows[1].1.is_err());
+
+        let text = "https://a.com\n\nhttps://b.com\n";
+        let rows = parse::<Row>(Format::Text, text);
+        assert_eq!(
+            rows.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+            [1, 3]
+        );
+
+        let json = r#"[{"url": "https://a.com", "weight": 2}, {"weight": 1}]"#;
+        let rows = parse::<Row>(Format::Json, json);
+        assert!(rows[0].1.is_ok() && rows[1].1.is_err());
+    }
+}
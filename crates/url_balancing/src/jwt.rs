@@ -1,9 +1,24 @@
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    env,
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use crate::{error::AppError, oauth::LinuxDoUser};
 
+/// 签名密钥，`JWT_SECRET`，必须设置；多个实例需要使用相同的值
+static SECRET: LazyLock<Vec<u8>> = LazyLock::new(|| match env::var("JWT_SECRET") {
+    Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+    _ => panic!("缺少环境变量 JWT_SECRET"),
+});
+
+/// 启动时检查签名密钥，避免第一次登录时才发现缺少配置
+pub fn init() {
+    LazyLock::force(&SECRET);
+}
+
 // 定义 JWT 的数据结构
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -25,7 +40,7 @@ pub fn create_jwt(user: LinuxDoUser) -> Result<String, AppError> {
     let token = encode(
         &Header::default(),
         &my_claims,
-        &EncodingKey::from_secret("secret".as_ref()),
+        &EncodingKey::from_secret(&SECRET),
     )?;
     Ok(token)
 }
@@ -34,8 +49,45 @@ pub fn create_jwt(user: LinuxDoUser) -> Result<String, AppError> {
 pub fn verify_jwt(token: &str) -> Result<TokenData<Claims>, AppError> {
     let token_data = decode::<Claims>(
         token,
-        &DecodingKey::from_secret("secret".as_ref()),
+        &DecodingKey::from_secret(&SECRET),
         &Validation::default(),
     )?;
     Ok(token_data)
 }
+
+// 访问受密码保护的 key 的凭证
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyClaims {
+    pub key: String,
+    /// 密码哈希的摘要，修改密码后旧凭证失效
+    pub version: String,
+    pub exp: u64,
+}
+
+// 生成 key 访问凭证，`ttl` 秒后过期
+pub fn create_key_jwt(key: &str, version: String, ttl: u64) -> Result<String, AppError> {
+    let claims = KeyClaims {
+        key: key.to_string(),
+        version,
+        exp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + ttl,
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(&SECRET),
+    )?)
+}
+
+// 验证 key 访问凭证
+pub fn verify_key_jwt(token: &str) -> Result<KeyClaims, AppError> {
+    let token_data = decode::<KeyClaims>(
+        token,
+        &DecodingKey::from_secret(&SECRET),
+        &Validation::default(),
+    )?;
+    Ok(token_data.claims)
+}
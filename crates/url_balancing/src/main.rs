@@ -1,20 +1,45 @@
+mod backup;
+mod balancer;
+mod circuit;
+mod cleanup;
+mod config;
 mod dao;
+mod egress;
 mod error;
 mod handler;
+mod health;
+mod import;
 mod jwt;
 mod middleware;
 mod oauth;
+mod password;
+mod proxy;
+mod request;
 mod routers;
+mod rules;
+mod schedule;
 mod state;
+mod sticky;
+mod target;
+mod template;
 mod token;
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
 #[tokio::main]
 async fn main() {
-    let app = routers::init_router().await;
+    jwt::init();
+    let state = Arc::new(state::AppState::init().await);
+    health::spawn(state.clone());
+    cleanup::spawn(state.clone());
+    let app = routers::init_router(state);
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let addr = SocketAddr::from(([127, 0, 0, 1], port.parse().unwrap()));
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
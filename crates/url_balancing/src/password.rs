@@ -0,0 +1,264 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    body,
+    extract::Request,
+    http::{
+        header::{CONTENT_TYPE, LOCATION, SET_COOKIE},
+        Method, StatusCode,
+    },
+    response::{Html, IntoResponse, Response},
+};
+use cookie::{time::Duration, Cookie};
+use entity::key;
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::{config::env_or, error::AppError, jwt, request::RequestInfo, state::AppState};
+
+const COOKIE_PREFIX: &str = "ub_auth_";
+/// 通过请求头提交密码，转发前移除
+const PASSWORD_HEADER: &str = "x-key-password";
+/// 通过查询参数提交密码，转发前移除
+const PASSWORD_QUERY: &str = "key_password";
+const FORM_FIELD: &str = "password";
+const MAX_FORM_BODY: usize = 4096;
+
+const FORM: &str = r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>需要密码</title>
+</head>
+<body style="font-family: sans-serif; display: flex; justify-content: center; margin-top: 20vh">
+<form method="post">
+<p>此链接需要密码才能访问</p>
+<p style="color: #c00">{error}</p>
+<input type="password" name="password" autofocus required>
+<button type="submit">确定</button>
+</form>
+</body>
+</html>
+"#;
+
+/// 访问密码配置
+pub struct PasswordConfig {
+    /// 验证通过后 cookie 的有效期，`PASSWORD_COOKIE_TTL` 秒
+    pub cookie_ttl: u64,
+    /// 窗口内允许的错误次数，`PASSWORD_MAX_ATTEMPTS`
+    pub max_attempts: u64,
+    /// 错误次数的统计窗口，`PASSWORD_ATTEMPT_WINDOW` 秒
+    pub window: u64,
+}
+
+impl PasswordConfig {
+    pub fn from_env() -> Self {
+        Self {
+            cookie_ttl: env_or("PASSWORD_COOKIE_TTL", 3600),
+            max_attempts: env_or("PASSWORD_MAX_ATTEMPTS", 5),
+            window: env_or("PASSWORD_ATTEMPT_WINDOW", 300),
+        }
+    }
+}
+
+/// 生成 argon2 哈希，计算量较大，放到阻塞线程池中执行
+pub async fn hash(password: String) -> Result<String, AppError> {
+    tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(|_| AppError::Unknown)?
+}
+
+pub async fn verify(hash: String, password: String) -> bool {
+    tokio::task::spawn_blocking(move || verify_password(&hash, &password))
+        .await
+        .unwrap_or(false)
+}
+
+/// 使用随机盐
+fn hash_password(password: &str) -> Result<String, AppError> {
+    let mut salt = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| AppError::Unknown)?;
+    let salt = SaltString::encode_b64(&salt).map_err(|_| AppError::Unknown)?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|_| AppError::Unknown)?;
+    Ok(hash.to_string())
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// 导入的哈希必须是 argon2 的 PHC 格式
+pub fn is_valid_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"))
+}
+
+/// 密码哈希的摘要，写入 cookie 凭证，修改密码后旧凭证失效
+fn version(hash: &str) -> String {
+    digest(&SHA256, hash.as_bytes()).as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn cookie_name(key: &str) -> String {
+    [COOKIE_PREFIX, key].concat()
+}
+
+fn cookie(key: &str, hash: &str, ttl: u64) -> Result<String, AppError> {
+    let token = jwt::create_key_jwt(key, version(hash), ttl)?;
+    Ok(Cookie::build((cookie_name(key), token))
+        .path("/")
+        .http_only(true)
+        .max_age(Duration::seconds(ttl as i64))
+        .to_string())
+}
+
+pub enum Access {
+    /// 验证通过，继续负载均衡，刚通过验证时附带需要设置的 cookie
+    Granted(Request, Option<String>),
+    /// 密码表单，或表单验证通过后跳回原地址
+    Respond(Response),
+}
+
+/// 依次检查 cookie 凭证、请求头或查询参数中的密码、表单提交的密码
+pub async fn authorize(
+    state: &AppState,
+    conf: &key::Model,
+    hash: &str,
+    req: &mut RequestInfo,
+    mut request: Request,
+) -> Result<Access, AppError> {
+    let submitted = req
+        .header(PASSWORD_HEADER)
+        .map(str::to_string)
+        .or_else(|| req.query_param(PASSWORD_QUERY));
+    strip(req, &mut request);
+
+    let version = version(hash);
+    if req
+        .cookie(&cookie_name(&conf.key))
+        .and_then(|token| jwt::verify_key_jwt(&token).ok())
+        .is_some_and(|claims| claims.key == conf.key && claims.version == version)
+    {
+        return Ok(Access::Granted(request, None));
+    }
+
+    if let Some(password) = submitted {
+        return Ok(if check(state, conf, hash, req, password).await? {
+            let cookie = cookie(&conf.key, hash, state.password.cookie_ttl)?;
+            Access::Granted(request, Some(cookie))
+        } else {
+            Access::Respond(form(Some("密码错误")))
+        });
+    }
+
+    let is_form = req
+        .header(CONTENT_TYPE.as_str())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if req.method == Method::POST && is_form {
+        let body = body::to_bytes(request.into_body(), MAX_FORM_BODY)
+            .await
+            .map_err(|_| AppError::Invalid)?;
+        let password = url::form_urlencoded::parse(&body)
+            .find(|(name, _)| name == FORM_FIELD)
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_default();
+        if !check(state, conf, hash, req, password).await? {
+            return Ok(Access::Respond(form(Some("密码错误"))));
+        }
+        // 用 303 跳回原地址，避免浏览器把表单重复提交给后端
+        let cookie = cookie(&conf.key, hash, state.password.cookie_ttl)?;
+        let response = Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(LOCATION, self_location(req))
+            .header(SET_COOKIE, cookie)
+            .body(body::Body::empty())
+            .map_err(|_| AppError::Invalid)?;
+        return Ok(Access::Respond(response));
+    }
+
+    Ok(Access::Respond(form(None)))
+}
+
+/// 先计数再校验，并发的请求也不能超过上限；超过上限时不再校验密码
+async fn check(
+    state: &AppState,
+    conf: &key::Model,
+    hash: &str,
+    req: &RequestInfo,
+    password: String,
+) -> Result<bool, AppError> {
+    // 没有客户端地址时无法按客户端限制，拒绝尝试
+    let client = req.ip.ok_or(AppError::Invalid)?.to_string();
+    let config = &state.password;
+    let attempts = state
+        .rdb
+        .add_attempt(&conf.key, &client, config.window)
+        .await?;
+    if attempts > config.max_attempts {
+        return Err(AppError::TooManyRequests);
+    }
+    if !verify(hash.to_string(), password).await {
+        return Ok(false);
+    }
+    state.rdb.clear_attempts(&conf.key, &client).await?;
+    Ok(true)
+}
+
+/// 移除请求中的密码，避免转发给后端
+fn strip(req: &mut RequestInfo, request: &mut Request) {
+    req.headers.remove(PASSWORD_HEADER);
+    request.headers_mut().remove(PASSWORD_HEADER);
+    if let Some(query) = req.query.take() {
+        let query: Vec<&str> = query
+            .split('&')
+            .filter(|pair| pair.split('=').next() != Some(PASSWORD_QUERY))
+            .collect();
+        req.query = Some(query.join("&")).filter(|query| !query.is_empty());
+    }
+}
+
+/// 服务前面可能有反向代理改写路径前缀，使用相对地址跳回当前页面
+fn self_location(req: &RequestInfo) -> String {
+    let segment = req.path.rsplit('/').next().unwrap_or_default();
+    match &req.query {
+        Some(query) => ["./", segment, "?", query].concat(),
+        None => ["./", segment].concat(),
+    }
+}
+
+fn form(error: Option<&str>) -> Response {
+    let page = FORM.replace("{error}", error.unwrap_or_default());
+    (StatusCode::UNAUTHORIZED, Html(page)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_and_verify() {
+        let hash = hash("mirror-pass".to_string()).await.unwrap();
+        assert!(is_valid_hash(&hash));
+        assert!(verify(hash.clone(), "mirror-pass".to_string()).await);
+        assert!(!verify(hash.clone(), "wrong".to_string()).await);
+        assert!(!is_valid_hash("plain-text"));
+        assert_ne!(
+            version(&hash),
+            version(&hash_password("mirror-pass").unwrap())
+        );
+    }
+}
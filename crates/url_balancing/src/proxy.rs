@@ -0,0 +1,195 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::{self, Body},
+    extract::Request,
+    http::{
+        header::{self, HeaderMap, HeaderName},
+        HeaderValue,
+    },
+    response::Response,
+};
+use cookie::Cookie;
+use entity::key;
+use reqwest::{redirect::Policy, Client};
+
+use crate::{
+    config::env_or,
+    egress::{self, PublicResolver},
+    error::AppError,
+    request::RequestInfo,
+    state::AppState,
+    target,
+};
+
+// 逐跳头部不应转发
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// 代理模式配置
+pub struct ProxyConfig {
+    pub client: Client,
+    /// 连接失败时换用其他后端重试的次数，`PROXY_RETRIES`
+    pub retries: usize,
+    /// 请求体上限，`PROXY_MAX_BODY` 字节，重试需要缓存请求体
+    pub max_body: usize,
+    /// 给后端响应加上 `Content-Security-Policy: sandbox`，`PROXY_SANDBOX`
+    ///
+    /// 代理的页面与管理接口同源，后端的脚本可以带着访问者的登录凭证调用接口；
+    /// 只有代理模式部署在独立的域名上时才应关闭
+    pub sandbox: bool,
+}
+
+impl ProxyConfig {
+    pub fn from_env() -> Self {
+        let client = Client::builder()
+            .redirect(Policy::none())
+            .dns_resolver(Arc::new(PublicResolver))
+            .timeout(Duration::from_secs(env_or("PROXY_TIMEOUT", 30)))
+            .build()
+            .expect("创建代理客户端失败");
+        Self {
+            client,
+            retries: env_or("PROXY_RETRIES", 2),
+            max_body: env_or("PROXY_MAX_BODY", 10 * 1024 * 1024),
+            sandbox: env_or("PROXY_SANDBOX", true),
+        }
+    }
+}
+
+/// 转发请求到选出的后端，返回实际使用的 url 和后端响应，没有可用后端时返回 404，
+/// 跳转次数用尽时返回 410
+pub async fn forward(
+    state: &AppState,
+    conf: &key::Model,
+    info: &RequestInfo,
+    request: Request,
+) -> Result<(String, Response), AppError> {
+    let config = &state.proxy;
+    let (parts, body) = request.into_parts();
+    let body = body::to_bytes(body, config.max_body)
+        .await
+        .map_err(|_| AppError::PayloadTooLarge)?;
+    let headers = forward_headers(&parts.headers, info);
+
+    let mut tried = Vec::new();
+    let mut consumed = false;
+    while tried.len() <= config.retries {
+        let Some(url) = state.get_url(conf, info, &tried).await? else {
+            // 一个请求都没有发出时交给调用方处理备用地址
+            if !consumed {
+                return Err(AppError::HTTPNotFound);
+            }
+            break;
+        };
+        // 主机部分的占位符没有取值时跳过
+        let target = match target::build(&url, conf, info) {
+            Err(AppError::HTTPNotFound) => {
+                tried.push(url);
+                continue;
+            }
+            target => target?,
+        };
+        // 域名由解析器过滤，IP 地址在这里检查
+        if !egress::is_allowed_url(&target) {
+            tried.push(url);
+            continue;
+        }
+        // 第一次发出请求前消耗跳转次数，重试不再重复消耗
+        if !consumed {
+            if !state.consume_hit(conf).await? {
+                return Err(AppError::Gone);
+            }
+            consumed = true;
+        }
+        let result = config
+            .client
+            .request(parts.method.clone(), target)
+            .headers(headers.clone())
+            .body(body.clone())
+            .send()
+            .await;
+        match result {
+            Ok(backend) => {
+                let mut response = Response::builder().status(backend.status());
+                for (name, value) in backend.headers() {
+                    if !HOP_BY_HOP.contains(name) && !is_reserved_cookie(name, value) {
+                        response = response.header(name, value);
+                    }
+                }
+                if config.sandbox {
+                    response = response.header(
+                        header::CONTENT_SECURITY_POLICY,
+                        "sandbox allow-scripts allow-forms allow-popups allow-downloads",
+                    );
+                }
+                let response = response
+                    .body(Body::from_stream(backend.bytes_stream()))
+                    .map_err(|_| AppError::BadGateway)?;
+                return Ok((url, response));
+            }
+            Err(err) if err.is_connect() => {
+                state.report_failure(&conf.key, &url, None).await?;
+                tried.push(url);
+            }
+            Err(_) => return Err(AppError::BadGateway),
+        }
+    }
+    if !consumed {
+        return Err(AppError::HTTPNotFound);
+    }
+    Err(AppError::BadGateway)
+}
+
+/// 后端不能设置本服务使用的 cookie，如登录凭证 `jwt` 和 `ub_` 开头的 cookie
+fn is_reserved_cookie(name: &HeaderName, value: &HeaderValue) -> bool {
+    name == header::SET_COOKIE
+        && value
+            .to_str()
+            .ok()
+            .and_then(|value| Cookie::parse(value).ok())
+            .is_none_or(|cookie| cookie.name() == "jwt" || cookie.name().starts_with("ub_"))
+}
+
+fn forward_headers(origin: &HeaderMap, info: &RequestInfo) -> HeaderMap {
+    let mut headers = HeaderMap::with_capacity(origin.len());
+    for (name, value) in origin {
+        if HOP_BY_HOP.contains(name)
+            || name == header::HOST
+            || name == header::CONTENT_LENGTH
+            || name == header::COOKIE
+        {
+            continue;
+        }
+        headers.append(name, value.clone());
+    }
+    // 登录凭证、密码凭证等 cookie 只属于本服务，不转发给后端
+    let cookies: Vec<String> = origin
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| Cookie::parse(cookie.trim()).ok())
+        .filter(|cookie| cookie.name() != "jwt" && !cookie.name().starts_with("ub_"))
+        .map(|cookie| cookie.stripped().to_string())
+        .collect();
+    if !cookies.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&cookies.join("; ")) {
+            headers.insert(header::COOKIE, value);
+        }
+    }
+    if let Some(ip) = info.ip.filter(|_| !headers.contains_key("x-forwarded-for")) {
+        if let Ok(value) = HeaderValue::from_str(&ip.to_string()) {
+            headers.insert("x-forwarded-for", value);
+        }
+    }
+    headers
+}
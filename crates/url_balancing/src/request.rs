@@ -0,0 +1,141 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::COOKIE, request::Parts, HeaderMap, Method},
+};
+use cookie::Cookie;
+
+use crate::config::env_or;
+
+/// 读取客户端地址的请求头，`CLIENT_IP_HEADER`，必须由可信的反向代理写入
+///
+/// 服务默认只监听本地，由反向代理转发；设为空时不读取请求头，直接使用连接地址
+static CLIENT_IP_HEADER: LazyLock<String> =
+    LazyLock::new(|| env_or("CLIENT_IP_HEADER", "x-real-ip".to_string()).to_lowercase());
+
+/// 请求头为 `X-Forwarded-For` 时，服务前面可信的反向代理层数，`TRUSTED_PROXIES`
+static TRUSTED_PROXIES: LazyLock<usize> = LazyLock::new(|| env_or("TRUSTED_PROXIES", 1));
+
+/// 负载均衡时用到的请求信息
+pub struct RequestInfo {
+    pub ip: Option<IpAddr>,
+    pub method: Method,
+    pub headers: HeaderMap,
+    /// 未解码的原始路径
+    pub path: String,
+    pub query: Option<String>,
+}
+
+impl RequestInfo {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        url::form_urlencoded::parse(self.query.as_deref()?.as_bytes())
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| Cookie::parse(cookie.trim()).ok())
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_string())
+    }
+
+    pub fn is_valid_attribute(attribute: &str) -> bool {
+        match attribute.split_once(':') {
+            None => attribute == "ip",
+            Some((kind, name)) => matches!(kind, "header" | "query" | "cookie") && !name.is_empty(),
+        }
+    }
+
+    /// 按 `ip`、`header:名称`、`query:名称`、`cookie:名称` 读取请求属性
+    pub fn attribute(&self, attribute: &str) -> Option<String> {
+        match attribute.split_once(':') {
+            None if attribute == "ip" => self.ip.map(|ip| ip.to_string()),
+            Some(("header", name)) => self.header(name).map(str::to_string),
+            Some(("query", name)) => self.query_param(name),
+            Some(("cookie", name)) => self.cookie(name),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RequestInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // 配置了请求头但取不到地址时不回退到代理的连接地址，避免所有客户端共用一个地址
+        let ip = if CLIENT_IP_HEADER.is_empty() {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip())
+        } else {
+            client_ip(&parts.headers, &CLIENT_IP_HEADER, *TRUSTED_PROXIES)
+        };
+        Ok(Self {
+            ip,
+            method: parts.method.clone(),
+            headers: parts.headers.clone(),
+            path: parts.uri.path().to_string(),
+            query: parts.uri.query().map(str::to_string),
+        })
+    }
+}
+
+/// 从指定的代理头中取客户端地址，只读取这一个请求头
+///
+/// `X-Forwarded-For` 最左边的值可以由客户端伪造，只信任可信代理从右边追加的部分，
+/// 条目数不足时视为没有地址
+fn client_ip(headers: &HeaderMap, header: &str, trusted_proxies: usize) -> Option<IpAddr> {
+    let mut values = headers
+        .get_all(header)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    if header != "x-forwarded-for" {
+        return values.next().and_then(|ip| ip.trim().parse().ok());
+    }
+    let forwarded: Vec<&str> = values.flat_map(|value| value.split(',')).collect();
+    forwarded
+        .iter()
+        .rev()
+        .nth(trusted_proxies.checked_sub(1)?)
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let xff = "x-forwarded-for";
+        let mut headers = HeaderMap::new();
+        headers.insert(xff, "6.6.6.6, 1.2.3.4".parse().unwrap());
+        assert_eq!(client_ip(&headers, xff, 1), "1.2.3.4".parse().ok());
+        assert_eq!(client_ip(&headers, xff, 2), "6.6.6.6".parse().ok());
+        assert_eq!(client_ip(&headers, xff, 3), None);
+        assert_eq!(client_ip(&headers, xff, 0), None);
+        // 只设置 X-Real-IP 的代理不会清理客户端发送的 X-Forwarded-For
+        assert_eq!(client_ip(&headers, "x-real-ip", 1), None);
+        headers.insert("x-real-ip", "1.2.3.4".parse().unwrap());
+        assert_eq!(client_ip(&headers, "x-real-ip", 1), "1.2.3.4".parse().ok());
+    }
+}
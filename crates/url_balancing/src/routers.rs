@@ -1,38 +1,40 @@
 use std::sync::Arc;
 
-use crate::{dao, handler::*, middleware, oauth::*, state};
+use crate::{handler::*, middleware, oauth::*, state::AppState};
 use axum::{
-    routing::{delete, get, post},
+    routing::{any, delete, get, post, put},
     Extension, Router,
 };
 use tower::ServiceBuilder;
 
-pub async fn init_router() -> Router {
-    let mdb_conn = dao::mysql::init::establish_connection().await.unwrap();
-    let mdb = dao::mysql::db::DbSrv::new(mdb_conn);
-    let rdb_conn = dao::redis::init::establish_connection();
-    let rdb = dao::redis::db::RdSrv::new(rdb_conn);
-    let oauth2_client = oauth2_client().unwrap();
-    let state = state::AppState {
-        mdb,
-        rdb,
-        oauth2_client,
-    };
+pub fn init_router(state: Arc<AppState>) -> Router {
     let cookie_layer = ServiceBuilder::new().layer(axum::middleware::from_fn(middleware::jwt_auth));
     let routes_with_auth = Router::new()
         .route("/key", post(create_key))
         .route("/key", get(get_keys))
-        .route("/:key/url", post(add_url))
-        .route("/:key/url", delete(delete_url))
+        .route(
+            "/key/:key",
+            get(get_key).patch(update_key).delete(delete_key),
+        )
+        .route("/key/:key/url", post(add_url))
+        .route("/key/:key/url", delete(delete_url).patch(update_url))
+        .route("/key/:key/urls", get(get_urls).put(replace_urls))
+        .route("/key/:key/import", post(import_urls))
+        .route("/key/:key/url/disabled", put(set_url_disabled))
+        .route("/export", get(export_keys))
+        .route("/import", post(import_keys))
         .route("/user", get(user_info))
         .layer(cookie_layer);
+    // 管理接口都在保留字 `key` 之下，`/:key/*rest` 之后的任意路径都交给负载均衡
     let router_without_auth = Router::new()
-        .route("/:key", post(url_balancing).get(url_balancing))
-        .route("/:key/urls", get(get_urls))
+        .route("/:key", any(url_balancing))
+        .route("/:key/", any(url_balancing))
+        .route("/:key/*rest", any(url_balancing))
+        .route("/key/:key/report", post(report_failure))
         .route("/auth/linuxdo", get(linuxdo_auth))
         .route("/auth/authorized", get(linuxdo_authorized));
     Router::new()
         .merge(routes_with_auth)
         .merge(router_without_auth)
-        .layer(Extension(Arc::new(state)))
+        .layer(Extension(state))
 }
@@ -0,0 +1,129 @@
+use entity::{
+    key::{Condition, Field, Op, Rule, Rules},
+    url,
+};
+
+use crate::{error::AppError, request::RequestInfo};
+
+/// 规则条数上限，每次请求都会逐条匹配
+const MAX_RULES: usize = 32;
+
+/// 检查规则的字段和分组名是否合法
+pub fn validate(rules: &Rules, is_valid_pool: impl Fn(&str) -> bool) -> Result<(), AppError> {
+    if rules.0.len() > MAX_RULES {
+        return Err(AppError::Invalid);
+    }
+    for rule in &rules.0 {
+        if !is_valid_pool(&rule.pool) || rule.attributes.keys().any(|name| name.is_empty()) {
+            return Err(AppError::Invalid);
+        }
+        for condition in &rule.conditions {
+            let named = matches!(condition.field, Field::Header | Field::Query);
+            if named
+                != condition
+                    .name
+                    .as_deref()
+                    .is_some_and(|name| !name.is_empty())
+            {
+                return Err(AppError::Invalid);
+            }
+            if condition.op != Op::Exists && condition.value.is_empty() {
+                return Err(AppError::Invalid);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 按顺序返回命中的规则
+pub fn matched<'a>(rules: &'a Rules, req: &'a RequestInfo) -> impl Iterator<Item = &'a Rule> {
+    rules.0.iter().filter(|rule| is_match(rule, req))
+}
+
+/// url 是否属于规则指定的分组并带有规则要求的属性
+pub fn targets(rule: &Rule, url: &url::Model) -> bool {
+    let attributes = url.attributes.as_ref().map(|attributes| &attributes.0);
+    url.pool == rule.pool
+        && rule.attributes.iter().all(|(name, value)| {
+            attributes
+                .and_then(|attributes| attributes.get(name))
+                .is_some_and(|actual| actual.eq_ignore_ascii_case(value))
+        })
+}
+
+fn is_match(rule: &Rule, req: &RequestInfo) -> bool {
+    rule.conditions
+        .iter()
+        .all(|condition| check(condition, req))
+}
+
+fn check(condition: &Condition, req: &RequestInfo) -> bool {
+    let name = condition.name.as_deref().unwrap_or_default();
+    let actual = match condition.field {
+        Field::UserAgent => req.header("user-agent").map(str::to_string),
+        Field::AcceptLanguage => req.header("accept-language").map(str::to_string),
+        Field::Header => req.header(name).map(str::to_string),
+        Field::Query => req.query_param(name),
+        Field::Method => Some(req.method.to_string()),
+    };
+    let Some(actual) = actual else {
+        return false;
+    };
+    let actual = actual.to_lowercase();
+    let expected = condition.value.to_lowercase();
+    match condition.op {
+        Op::Equals => actual == expected,
+        Op::Contains => actual.contains(&expected),
+        Op::Prefix => actual.starts_with(&expected),
+        Op::Suffix => actual.ends_with(&expected),
+        Op::Exists => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, Method};
+
+    fn rule(field: Field, name: Option<&str>, op: Op, value: &str, pool: &str) -> Rule {
+        Rule {
+            conditions: vec![Condition {
+                field,
+                name: name.map(str::to_string),
+                op,
+                value: value.to_string(),
+            }],
+            pool: pool.to_string(),
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_matched() {
+        let rules = Rules(vec![
+            rule(Field::UserAgent, None, Op::Contains, "android", "android"),
+            rule(Field::UserAgent, None, Op::Contains, "iphone", "ios"),
+            rule(Field::Query, Some("beta"), Op::Exists, "", "beta"),
+            rule(Field::Method, None, Op::Equals, "post", "write"),
+        ]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "user-agent",
+            "Mozilla/5.0 (Linux; Android 14)".parse().unwrap(),
+        );
+        let req = RequestInfo {
+            ip: None,
+            method: Method::GET,
+            headers,
+            path: "/abc".to_string(),
+            query: Some("beta=1".to_string()),
+        };
+        let pools: Vec<&str> = matched(&rules, &req)
+            .map(|rule| rule.pool.as_str())
+            .collect();
+        assert_eq!(pools, ["android", "beta"]);
+        assert!(validate(&rules, |pool| !pool.is_empty()).is_ok());
+        let invalid = Rules(vec![rule(Field::Header, None, Op::Equals, "x", "a")]);
+        assert!(validate(&invalid, |pool| !pool.is_empty()).is_err());
+    }
+}
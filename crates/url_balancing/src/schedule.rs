@@ -0,0 +1,105 @@
+use chrono::{DateTime, Datelike, Duration, NaiveTime};
+use chrono_tz::Tz;
+use entity::url::{Schedule, Weekly};
+
+use crate::error::AppError;
+
+/// 检查时间窗口是否合法
+pub fn validate(schedule: &Schedule) -> Result<(), AppError> {
+    if let (Some(start), Some(end)) = (schedule.start, schedule.end) {
+        if start >= end {
+            return Err(AppError::Invalid);
+        }
+    }
+    if let Some(weekly) = &schedule.weekly {
+        if weekly.days.iter().any(|day| !(1..=7).contains(day)) {
+            return Err(AppError::Invalid);
+        }
+        let (from, to) = (time(&weekly.from)?, time(&weekly.to)?);
+        if from == to {
+            return Err(AppError::Invalid);
+        }
+        weekly
+            .timezone
+            .parse::<Tz>()
+            .map_err(|_| AppError::Invalid)?;
+    }
+    Ok(())
+}
+
+/// `now` 时刻 url 是否处于生效时间窗口内，配置无法解析时视为不生效
+pub fn is_active(schedule: &Schedule, now: i64) -> bool {
+    if schedule.start.is_some_and(|start| now < start) || schedule.end.is_some_and(|end| now >= end)
+    {
+        return false;
+    }
+    match &schedule.weekly {
+        Some(weekly) => in_weekly(weekly, now).unwrap_or(false),
+        None => true,
+    }
+}
+
+fn in_weekly(weekly: &Weekly, now: i64) -> Result<bool, AppError> {
+    let tz: Tz = weekly.timezone.parse().map_err(|_| AppError::Invalid)?;
+    let local = DateTime::from_timestamp(now, 0)
+        .ok_or(AppError::Invalid)?
+        .with_timezone(&tz);
+    let (from, to) = (time(&weekly.from)?, time(&weekly.to)?);
+    let current = local.time();
+    // 跨越午夜的时段，凌晨部分属于前一天的时段
+    let day = if from <= to {
+        if current < from || current >= to {
+            return Ok(false);
+        }
+        local.date_naive()
+    } else if current >= from {
+        local.date_naive()
+    } else if current < to {
+        local.date_naive() - Duration::days(1)
+    } else {
+        return Ok(false);
+    };
+    let weekday = day.weekday().number_from_monday() as u8;
+    Ok(weekly.days.is_empty() || weekly.days.contains(&weekday))
+}
+
+fn time(value: &str) -> Result<NaiveTime, AppError> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| AppError::Invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_active() {
+        let weekly = |from: &str, to: &str| Schedule {
+            start: None,
+            end: None,
+            weekly: Some(Weekly {
+                days: vec![1, 2, 3, 4, 5],
+                from: from.to_string(),
+                to: to.to_string(),
+                timezone: "Asia/Shanghai".to_string(),
+            }),
+        };
+        // 2026-10-16 周五 10:00 +08:00
+        let friday = 1_792_116_000;
+        let office = weekly("09:00", "18:00");
+        assert!(validate(&office).is_ok());
+        assert!(is_active(&office, friday));
+        assert!(!is_active(&office, friday + 9 * 3600));
+        // 周五 23:00 开始的时段持续到周六凌晨
+        let night = weekly("22:00", "06:00");
+        assert!(is_active(&night, friday + 16 * 3600));
+        assert!(!is_active(&night, friday + 40 * 3600));
+        let window = Schedule {
+            start: Some(friday),
+            end: Some(friday + 60),
+            weekly: None,
+        };
+        assert!(is_active(&window, friday));
+        assert!(!is_active(&window, friday + 60));
+        assert!(validate(&weekly("9:00", "25:00")).is_err());
+    }
+}
@@ -1,20 +1,77 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use chrono::Utc;
+
+use entity::{
+    key::{self, Sticky, Strategy},
+    url,
+};
 use oauth2::basic::BasicClient;
 
 use crate::{
+    balancer,
+    circuit::{self, CircuitConfig, CircuitState},
+    config::env_or,
+    dao,
     dao::{mysql::db::DbSrv, redis::db::RdSrv},
     error::AppError,
+    oauth::oauth2_client,
+    password::PasswordConfig,
+    proxy::ProxyConfig,
+    request::RequestInfo,
+    rules, schedule, sticky, template,
+    token::TokenConfig,
 };
 
 pub struct AppState {
     pub mdb: DbSrv,
     pub rdb: RdSrv,
     pub oauth2_client: BasicClient,
+    pub circuit: CircuitConfig,
+    pub proxy: ProxyConfig,
+    pub token: TokenConfig,
+    pub password: PasswordConfig,
+    /// 全局备用地址，`FALLBACK_URL`，key 没有设置备用地址时使用
+    pub fallback_url: Option<String>,
+}
+
+fn fallback_url_from_env() -> Option<String> {
+    let url = env_or("FALLBACK_URL", String::new());
+    if url.is_empty() {
+        return None;
+    }
+    match template::sample(&url) {
+        Ok(parsed) if !template::is_template(&url) => Some(parsed.to_string()),
+        _ => panic!("无效的环境变量 FALLBACK_URL"),
+    }
 }
 
 impl AppState {
+    pub async fn init() -> Self {
+        let mdb_conn = dao::mysql::init::establish_connection().await.unwrap();
+        let mdb = DbSrv::new(mdb_conn);
+        let rdb_conn = dao::redis::init::establish_connection();
+        let rdb = RdSrv::new(rdb_conn);
+        let oauth2_client = oauth2_client().unwrap();
+        Self {
+            mdb,
+            rdb,
+            oauth2_client,
+            circuit: CircuitConfig::from_env(),
+            proxy: ProxyConfig::from_env(),
+            token: TokenConfig::from_env(),
+            password: PasswordConfig::from_env(),
+            fallback_url: fallback_url_from_env(),
+        }
+    }
+
     pub async fn add_key(&self, uid: i64, key: &str, limitation: i16) -> Result<(), AppError> {
         self.rdb.add_key(uid, key, limitation).await?;
-        self.mdb.add_key(uid, key).await?;
+        // MySQL 写入失败（如 key 已被占用）时撤销，避免用户获得不属于自己的 key
+        if let Err(err) = self.mdb.add_key(uid, key).await {
+            self.rdb.remove_user_key(uid, key).await?;
+            return Err(err);
+        }
         Ok(())
     }
 
@@ -22,52 +79,259 @@ impl AppState {
         if self.rdb.check_key(uid, key).await? {
             return Ok(true);
         }
-        if self.mdb.check_key(key).await?.is_some() {
-            self.rdb.add_key(uid.unwrap(), key, 100).await?;
-            return Ok(true);
+        if let Some(conf) = self.mdb.check_key(key).await? {
+            self.rdb.add_key(conf.user_id, key, 100).await?;
+            return Ok(uid.is_none_or(|uid| uid == conf.user_id));
         }
         Ok(false)
     }
 
-    pub async fn get_url(&self, key: &str) -> Result<Option<String>, AppError> {
-        let url = self.rdb.get_url(key).await?;
-        if url.is_some() {
-            return Ok(url);
+    pub async fn delete_key(&self, uid: i64, key: &str) -> Result<(), AppError> {
+        let urls = self.mdb.delete_key(key).await?;
+        self.rdb.delete_key(uid, key, &urls).await
+    }
+
+    pub fn is_expired(&self, conf: &key::Model) -> bool {
+        conf.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+
+    /// 跳转次数用尽时返回 false，否则消耗一次跳转次数；选出 url 之后再调用，
+    /// 没有可用 url 的请求不占用次数
+    pub async fn consume_hit(&self, conf: &key::Model) -> Result<bool, AppError> {
+        match conf.max_hits {
+            Some(max_hits) => self.rdb.consume_hit(&conf.key, max_hits).await,
+            None => Ok(true),
         }
+    }
+
+    pub async fn get_key(&self, key: &str) -> Result<Option<key::Model>, AppError> {
+        if let Some(conf) = self.rdb.get_key_conf(key).await? {
+            return Ok(Some(conf));
+        }
+        let conf = self.mdb.get_key(key).await?;
+        if let Some(conf) = &conf {
+            self.rdb.set_key_conf(conf).await?;
+        }
+        Ok(conf)
+    }
+
+    /// 跳转次数上限的值有变化时重新计数
+    pub async fn update_key(&self, key: key::ActiveModel) -> Result<key::Model, AppError> {
+        let reset_hits = key.max_hits.is_set();
+        let key = self.mdb.update_key(key).await?;
+        self.rdb.set_key_conf(&key).await?;
+        if reset_hits {
+            self.rdb.reset_hits(&key.key).await?;
+        }
+        Ok(key)
+    }
+
+    async fn get_pool(&self, key: &str) -> Result<Vec<url::Model>, AppError> {
+        match self.rdb.get_pool(key).await? {
+            Some(pool) => Ok(pool),
+            None => self.load_urls(key).await,
+        }
+    }
+
+    /// 缓存未加载时从 MySQL 读取并重建缓存
+    async fn load_urls(&self, key: &str) -> Result<Vec<url::Model>, AppError> {
+        let generation = self.rdb.url_generation(key).await?;
         let urls = self.mdb.get_urls(key).await?;
-        if urls.is_empty() {
-            return Ok(None);
+        self.rdb.load_urls(key, generation, &urls).await?;
+        Ok(urls)
+    }
+
+    /// 选出一个可用的 url，`exclude` 中的 url 不参与选择
+    pub async fn get_url(
+        &self,
+        conf: &key::Model,
+        req: &RequestInfo,
+        exclude: &[String],
+    ) -> Result<Option<String>, AppError> {
+        let mut pool = self.get_pool(&conf.key).await?;
+        pool.retain(|url| !url.disabled && !exclude.contains(&url.url) && url.weight > 0);
+        let health = self.rdb.get_health(&conf.key).await?;
+        pool.retain(|url| health.get(&url.url) != Some(&false));
+        let now = circuit::now();
+        pool.retain(|url| {
+            url.schedule
+                .as_ref()
+                .is_none_or(|schedule| schedule::is_active(schedule, now as i64))
+        });
+        let mut half_open = HashMap::new();
+        for (url, until) in self.rdb.get_ejections(&conf.key).await? {
+            match self.circuit.state(until, now) {
+                CircuitState::Open => pool.retain(|u| u.url != url),
+                CircuitState::HalfOpen => {
+                    half_open.insert(url, until);
+                }
+                CircuitState::Closed => self.rdb.close_circuit(&conf.key, &url).await?,
+            }
+        }
+        loop {
+            let candidates = narrow(conf, req, pool.clone());
+            let url = self.select_url(conf, req, &candidates).await?;
+            // 选中半开的 url 时才领取试探机会，用完后等待半开期结束，期间没有失败则恢复
+            if let Some((url, until)) = url.as_ref().and_then(|url| half_open.get_key_value(url)) {
+                if self
+                    .rdb
+                    .take_trial(&conf.key, url, *until, self.circuit.window)
+                    .await?
+                    > self.circuit.trial_requests
+                {
+                    pool.retain(|u| u.url != *url);
+                    continue;
+                }
+            }
+            if let Some(url) = &url {
+                self.rdb
+                    .record_request(&conf.key, url, self.circuit.window)
+                    .await?;
+            }
+            return Ok(url);
         }
-        for url in urls {
-            self.rdb.add_url(key, &url).await?;
+    }
+
+    async fn select_url(
+        &self,
+        conf: &key::Model,
+        req: &RequestInfo,
+        pool: &[url::Model],
+    ) -> Result<Option<String>, AppError> {
+        match conf.sticky {
+            Sticky::Off => {}
+            // 最高随机权重哈希保证 url 仍在池中时客户端不会漂移
+            Sticky::Ip => {
+                if let Some(ip) = req.ip {
+                    let url = balancer::pick_hashed(pool, &ip.to_string());
+                    return Ok(url.map(|url| url.url.clone()));
+                }
+            }
+            Sticky::Cookie => {
+                let digest = req.cookie(&sticky::cookie_name(&conf.key));
+                let url = digest.and_then(|digest| {
+                    pool.iter()
+                        .find(|url| url.weight > 0 && sticky::url_digest(&url.url) == digest)
+                });
+                if let Some(url) = url {
+                    return Ok(Some(url.url.clone()));
+                }
+            }
         }
-        Box::pin(self.get_url(key)).await
+        let url = match conf.strategy {
+            Strategy::Random => balancer::pick_weighted(pool).map(|url| url.url.clone()),
+            Strategy::RoundRobin => {
+                let counter = self.rdb.next_round_robin(&conf.key).await?;
+                balancer::pick_round_robin(pool, counter).map(|url| url.url.clone())
+            }
+            Strategy::Weighted => self.rdb.next_smooth_weighted(&conf.key, pool).await?,
+            Strategy::ConsistentHash => {
+                match req.attribute(conf.hash_on.as_deref().unwrap_or("ip")) {
+                    Some(attribute) => balancer::pick_hashed(pool, &attribute),
+                    None => balancer::pick_weighted(pool),
+                }
+                .map(|url| url.url.clone())
+            }
+        };
+        Ok(url)
     }
 
-    pub async fn add_url(&self, key: &str, url: &str) -> Result<(), AppError> {
-        self.rdb.add_url(key, url).await?;
-        self.mdb.add_url(key, url).await?;
+    pub async fn add_url(&self, key: &str, url: url::ActiveModel) -> Result<(), AppError> {
+        let url = self.mdb.add_url(key, url).await?;
+        self.rdb.add_url(key, &url).await?;
         Ok(())
     }
 
+    pub async fn add_urls(&self, key: &str, urls: Vec<url::ActiveModel>) -> Result<(), AppError> {
+        let urls = self.mdb.add_urls(key, urls).await?;
+        self.rdb.replace_urls(key, &[], &urls, false).await
+    }
+
     pub async fn delete_url(&self, key: &str, url: &str) -> Result<(), AppError> {
         self.mdb.delete_url(key, url).await?;
         self.rdb.delete_url(key, url).await?;
         Ok(())
     }
 
-    pub async fn get_urls(&self, key: &str) -> Result<Vec<String>, AppError> {
-        let urls = self.rdb.get_urls(key).await?;
-        if urls.is_empty() {
-            let urls = self.mdb.get_urls(key).await?;
-            for url in &urls {
-                self.rdb.add_url(key, url).await?;
-            }
-            return Ok(urls);
-        }
+    /// 修改单个 url，地址变化时同时清理旧地址的状态
+    pub async fn update_url(
+        &self,
+        key: &str,
+        url: &str,
+        active: url::ActiveModel,
+    ) -> Result<(), AppError> {
+        let updated = self.mdb.update_url(url, active).await?;
+        let removed = if updated.url == url {
+            vec![]
+        } else {
+            vec![url.to_string()]
+        };
+        self.rdb
+            .replace_urls(key, &removed, &[updated], false)
+            .await
+    }
+
+    /// 替换整个 url 池，MySQL 事务和 Redis 事务分别保证两侧的原子性
+    pub async fn replace_urls(
+        &self,
+        key: &str,
+        urls: Vec<url::ActiveModel>,
+    ) -> Result<Vec<url::Model>, AppError> {
+        let old = self.mdb.get_urls(key).await?;
+        let urls = self.mdb.replace_urls(key, urls).await?;
+        let removed: Vec<String> = old
+            .into_iter()
+            .map(|url| url.url)
+            .filter(|old| !urls.iter().any(|url| &url.url == old))
+            .collect();
+        self.rdb.replace_urls(key, &removed, &urls, true).await?;
         Ok(urls)
     }
 
+    pub async fn find_url(&self, key: &str, url: &str) -> Result<Option<url::Model>, AppError> {
+        self.mdb.get_url(key, url).await
+    }
+
+    /// 停用的 url 从选择集合中移除，但保留属性和健康状态
+    pub async fn set_url_disabled(
+        &self,
+        key: &str,
+        url: &str,
+        disabled: bool,
+    ) -> Result<(), AppError> {
+        let url = self.mdb.set_url_disabled(key, url, disabled).await?;
+        self.rdb.add_url(key, &url).await?;
+        Ok(())
+    }
+
+    pub async fn get_urls(&self, key: &str) -> Result<Vec<url::Model>, AppError> {
+        match self.rdb.get_urls(key).await? {
+            Some(urls) => Ok(urls),
+            None => self.load_urls(key).await,
+        }
+    }
+
+    /// 上报 url 访问失败，只接受池中的 url；`reporter` 为上报的客户端地址，
+    /// 同一窗口内每个地址只计一次
+    pub async fn report_failure(
+        &self,
+        key: &str,
+        url: &str,
+        reporter: Option<IpAddr>,
+    ) -> Result<bool, AppError> {
+        if !self.get_urls(key).await?.iter().any(|u| u.url == url) {
+            return Err(AppError::Invalid);
+        }
+        let reporter = reporter.map(|ip| ip.to_string());
+        self.rdb
+            .report_failure(key, url, reporter.as_deref(), &self.circuit)
+            .await
+    }
+
+    pub async fn get_health(&self, key: &str) -> Result<HashMap<String, bool>, AppError> {
+        self.rdb.get_health(key).await
+    }
+
     pub async fn set_csrf(&self, csrf: &str) -> Result<(), AppError> {
         self.rdb.set_csrf(csrf).await
     }
@@ -89,3 +353,37 @@ impl AppState {
         Ok(keys_str)
     }
 }
+
+/// 按规则、灰度分组和优先级缩小候选范围
+fn narrow(conf: &key::Model, req: &RequestInfo, mut pool: Vec<url::Model>) -> Vec<url::Model> {
+    // 命中的规则优先于灰度分流，规则指向的 url 都不可用时继续匹配
+    let matched = conf.rules.as_ref().and_then(|rules| {
+        rules::matched(rules, req).find(|rule| pool.iter().any(|url| rules::targets(rule, url)))
+    });
+    if let Some(rule) = matched {
+        pool.retain(|url| rules::targets(rule, url));
+    } else {
+        let pool_name = if conf.canary_percent > 0 {
+            let client = conf.canary_sticky.then_some(req.ip).flatten();
+            let client = client.map(|ip| ip.to_string());
+            if balancer::in_canary(conf.canary_percent, &conf.key, client.as_deref()) {
+                url::CANARY_POOL
+            } else {
+                url::DEFAULT_POOL
+            }
+        } else {
+            url::DEFAULT_POOL
+        };
+        // 选中的分组没有可用 url 时只在默认和灰度分组之间兜底，规则专用的分组不参与
+        if pool.iter().any(|url| url.pool == pool_name) {
+            pool.retain(|url| url.pool == pool_name);
+        } else {
+            pool.retain(|url| url.pool == url::DEFAULT_POOL || url.pool == url::CANARY_POOL);
+        }
+    }
+    // 只使用仍有可用 url 的最高优先级分组
+    if let Some(top) = pool.iter().map(|url| url.priority).min() {
+        pool.retain(|url| url.priority == top);
+    }
+    pool
+}
@@ -0,0 +1,25 @@
+use cookie::{time::Duration, Cookie};
+use ring::digest::{digest, SHA256};
+
+const COOKIE_PREFIX: &str = "ub_sticky_";
+
+pub fn cookie_name(key: &str) -> String {
+    [COOKIE_PREFIX, key].concat()
+}
+
+/// cookie 中只保存 url 的摘要，避免暴露完整地址
+pub fn url_digest(url: &str) -> String {
+    let hash = digest(&SHA256, url.as_bytes());
+    hash.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub fn cookie(key: &str, url: &str) -> String {
+    Cookie::build((cookie_name(key), url_digest(url)))
+        .path("/")
+        .http_only(true)
+        .max_age(Duration::days(1))
+        .to_string()
+}
@@ -0,0 +1,145 @@
+use entity::key::{self, PathMode};
+use url::Url;
+
+use crate::{
+    error::AppError,
+    request::RequestInfo,
+    template::{self, Context},
+};
+
+/// 按 key 的路径拼接规则，由后端 url 和请求生成最终地址
+pub fn build(url: &str, conf: &key::Model, req: &RequestInfo) -> Result<Url, AppError> {
+    // 使用原始路径，避免解码后的 `%2F` 等字符改变路径结构
+    let rest = req
+        .path
+        .strip_prefix('/')
+        .and_then(|path| path.strip_prefix(conf.key.as_str()))
+        .unwrap_or_default();
+    let mut target = if template::is_template(url) {
+        let ctx = Context {
+            key: &conf.key,
+            rest,
+            req,
+        };
+        Url::parse(&template::render(url, &ctx)?)?
+    } else {
+        Url::parse(url)?
+    };
+    // 模板中已经引用的部分不再重复拼接
+    let rest = Some(rest).filter(|rest| !rest.is_empty() && !template::references(url, "path"));
+    let query = req
+        .query
+        .as_deref()
+        .filter(|query| !query.is_empty() && !template::references(url, "query"));
+    match conf.path_mode {
+        PathMode::Ignore => {}
+        PathMode::Append => {
+            if let Some(rest) = rest {
+                let path = [target.path().trim_end_matches('/'), rest].concat();
+                target.set_path(&path);
+            }
+            if let Some(query) = query {
+                let query = match target.query().filter(|origin| !origin.is_empty()) {
+                    Some(origin) => [origin, "&", query].concat(),
+                    None => query.to_string(),
+                };
+                target.set_query(Some(&query));
+            }
+        }
+        // 模板引用了路径或查询参数时保留渲染结果
+        PathMode::Replace => {
+            if !template::references(url, "path") {
+                target.set_path(rest.unwrap_or("/"));
+            }
+            if !template::references(url, "query") {
+                target.set_query(query);
+            }
+        }
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, Method};
+
+    fn conf(path_mode: PathMode) -> key::Model {
+        key::Model {
+            id: 1,
+            user_id: 1,
+            key: "abc".to_string(),
+            strategy: Default::default(),
+            hash_on: None,
+            sticky: Default::default(),
+            mode: Default::default(),
+            path_mode,
+            redirect_status: 307,
+            canary_percent: 0,
+            canary_sticky: false,
+            rules: None,
+            name: None,
+            description: None,
+            tags: None,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+            expires_at: None,
+            max_hits: None,
+            fallback_url: None,
+            disabled: false,
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let req = RequestInfo {
+            ip: None,
+            method: Method::GET,
+            headers: HeaderMap::new(),
+            path: "/abc/v1/x.zip".to_string(),
+            query: Some("sig=1".to_string()),
+        };
+        let cases = [
+            (
+                PathMode::Append,
+                "https://a.com/base/?t=0",
+                "https://a.com/base/v1/x.zip?t=0&sig=1",
+            ),
+            (
+                PathMode::Replace,
+                "https://a.com/base?t=0",
+                "https://a.com/v1/x.zip?sig=1",
+            ),
+            (
+                PathMode::Ignore,
+                "https://a.com/base?t=0",
+                "https://a.com/base?t=0",
+            ),
+            (
+                PathMode::Append,
+                "https://a.com/{path}?q={query}",
+                "https://a.com/v1/x.zip?q=sig=1",
+            ),
+            (
+                PathMode::Replace,
+                "https://a.com/m/{path}?{query}",
+                "https://a.com/m/v1/x.zip?sig=1",
+            ),
+            (
+                PathMode::Replace,
+                "https://a.com/dl?k={key}",
+                "https://a.com/v1/x.zip?sig=1",
+            ),
+            (
+                PathMode::Ignore,
+                "https://a.com/{key}/{path}",
+                "https://a.com/abc/v1/x.zip",
+            ),
+        ];
+        for (path_mode, url, expected) in cases {
+            let target = build(url, &conf(path_mode), &req).unwrap();
+            assert_eq!(target.as_str(), expected, "{path_mode:?} {url}");
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::LazyLock;
+
+use url::{form_urlencoded::byte_serialize, Url};
+
+use crate::{config::env_or, error::AppError, request::RequestInfo};
+
+/// `{region}` 取值的请求头，`TEMPLATE_REGION_HEADER`，通常由 CDN 写入
+static REGION_HEADER: LazyLock<String> =
+    LazyLock::new(|| env_or("TEMPLATE_REGION_HEADER", "cf-ipcountry".to_string()));
+
+/// 渲染模板所需的请求信息
+pub struct Context<'a> {
+    pub key: &'a str,
+    /// key 之后的原始路径，以 `/` 开头
+    pub rest: &'a str,
+    pub req: &'a RequestInfo,
+}
+
+pub fn is_template(url: &str) -> bool {
+    url.contains('{')
+}
+
+/// 模板中是否引用了占位符 `name`，`{query.x}` 视为引用了 `query`
+pub fn references(url: &str, name: &str) -> bool {
+    placeholders(url)
+        .unwrap_or_default()
+        .iter()
+        .any(|placeholder| placeholder.split('.').next() == Some(name))
+}
+
+/// 检查占位符是否合法，并用示例值渲染后确认是有效的 http(s) 地址
+pub fn validate(url: &str) -> Result<(), AppError> {
+    sample(url).map(|_| ())
+}
+
+/// 用示例值渲染模板，非模板直接解析
+pub fn sample(url: &str) -> Result<Url, AppError> {
+    // 协议和主机部分只允许取值受限的 `{region}`，避免请求中的值改变目标主机
+    if placeholders(&url[..authority_end(url)])?
+        .iter()
+        .any(|placeholder| *placeholder != "region")
+    {
+        return Err(AppError::Invalid);
+    }
+    let mut rendered = url.to_string();
+    for placeholder in placeholders(url)? {
+        let sample = match placeholder.split_once('.') {
+            None if matches!(placeholder, "key" | "path" | "query" | "ip" | "region") => "x",
+            Some(("query" | "header" | "cookie", name)) if !name.is_empty() => "x",
+            _ => return Err(AppError::Invalid),
+        };
+        rendered = rendered.replace(&["{", placeholder, "}"].concat(), sample);
+    }
+    let parsed = Url::parse(&rendered)?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+        return Err(AppError::Invalid);
+    }
+    Ok(parsed)
+}
+
+/// 用请求信息替换占位符，缺失的值替换为空字符串
+///
+/// 主机部分的占位符没有取值时返回 [`AppError::HTTPNotFound`]，这个 url 视为不可用
+pub fn render(url: &str, ctx: &Context) -> Result<String, AppError> {
+    let authority_end = authority_end(url);
+    let mut rendered = String::with_capacity(url.len());
+    let mut rest = url;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or(AppError::Invalid)? + start;
+        let in_authority = url.len() - rest.len() + start < authority_end;
+        let value = value(&rest[start + 1..end], ctx);
+        if in_authority && value.is_none() {
+            return Err(AppError::HTTPNotFound);
+        }
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&value.unwrap_or_default());
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// 协议和主机部分的结束位置
+fn authority_end(url: &str) -> usize {
+    url.find("://").map_or(url.len(), |start| {
+        let rest = &url[start + 3..];
+        start + 3 + rest.find(['/', '?', '#']).unwrap_or(rest.len())
+    })
+}
+
+fn placeholders(url: &str) -> Result<Vec<&str>, AppError> {
+    let mut names = Vec::new();
+    let mut rest = url;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or(AppError::Invalid)? + start;
+        let name = &rest[start + 1..end];
+        if name.is_empty() || name.contains('{') {
+            return Err(AppError::Invalid);
+        }
+        names.push(name);
+        rest = &rest[end + 1..];
+    }
+    if rest.contains('}') {
+        return Err(AppError::Invalid);
+    }
+    Ok(names)
+}
+
+fn value(placeholder: &str, ctx: &Context) -> Option<String> {
+    let encode = |value: &str| byte_serialize(value.as_bytes()).collect::<String>();
+    match placeholder.split_once('.') {
+        None => match placeholder {
+            "key" => Some(ctx.key.to_string()),
+            // 原始路径已经过编码，直接使用
+            "path" => Some(ctx.rest.trim_start_matches('/').to_string()),
+            "query" => ctx.req.query.clone(),
+            "ip" => ctx.req.ip.map(|ip| ip.to_string()),
+            // 可能出现在主机名中，只接受字母、数字和 `-`
+            "region" => ctx
+                .req
+                .header(&REGION_HEADER)
+                .map(str::to_lowercase)
+                .filter(|region| {
+                    !region.is_empty()
+                        && region
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                }),
+            _ => None,
+        },
+        Some(("query", name)) => ctx.req.query_param(name).map(|v| encode(&v)),
+        Some(("header", name)) => ctx.req.header(name).map(encode),
+        Some(("cookie", name)) => ctx.req.cookie(name).map(|v| encode(&v)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, Method};
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("https://{region}.cdn.example.com/{path}?u={query.user}").is_ok());
+        assert!(validate("https://mirror.example.com/files").is_ok());
+        assert!(validate("https://{unknown}.example.com").is_err());
+        assert!(validate("https://{path.example.com").is_err());
+        assert!(validate("ftp://mirror.example.com").is_err());
+        assert!(validate("https://{path}.example.com").is_err());
+        assert!(validate("https://mirror.example.com{path}").is_err());
+        assert!(validate("https://{query.host}/x").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cf-ipcountry", "HK".parse().unwrap());
+        let req = RequestInfo {
+            ip: Some("10.0.0.1".parse().unwrap()),
+            method: Method::GET,
+            headers,
+            path: "/abc/v1/x.zip".to_string(),
+            query: Some("user=a%20b&sig=1".to_string()),
+        };
+        let ctx = Context {
+            key: "abc",
+            rest: "/v1/x.zip",
+            req: &req,
+        };
+        let url = "https://{region}.cdn.example.com/{path}?u={query.user}&ip={ip}";
+        assert_eq!(
+            render(url, &ctx).unwrap(),
+            "https://hk.cdn.example.com/v1/x.zip?u=a+b&ip=10.0.0.1"
+        );
+
+        let mut req = req;
+        req.headers
+            .insert("cf-ipcountry", "evil.com/x?".parse().unwrap());
+        let ctx = Context {
+            key: "abc",
+            rest: "/v1/x.zip",
+            req: &req,
+        };
+        assert!(matches!(
+            render("https://{region}.cdn.example.com/f", &ctx),
+            Err(AppError::HTTPNotFound)
+        ));
+        assert_eq!(
+            render("https://cdn.example.com/{region}/f", &ctx).unwrap(),
+            "https://cdn.example.com//f"
+        );
+    }
+}
@@ -1,11 +1,136 @@
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::str::FromStr;
+
 use ring::rand::{SecureRandom, SystemRandom};
 
-/// 生成指定长度的URL安全Token
-pub fn new_token() -> String {
+use crate::config::env_or;
+
+/// 随机 key 使用的字符集
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alphabet {
+    /// URL 安全的 base64 字符，与旧版本生成的 key 一致
+    Base64Url,
+    Base62,
+    /// 去掉了 `0OIl` 等易混淆字符，适合印刷和短信
+    Base58,
+    /// 小写字母和数字
+    Lowercase,
+}
+
+impl Alphabet {
+    fn chars(self) -> &'static [u8] {
+        match self {
+            Self::Base64Url => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+            Self::Base62 => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+            Self::Base58 => b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+            Self::Lowercase => b"abcdefghijklmnopqrstuvwxyz0123456789",
+        }
+    }
+}
+
+impl FromStr for Alphabet {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "base64url" => Ok(Self::Base64Url),
+            "base62" => Ok(Self::Base62),
+            "base58" => Ok(Self::Base58),
+            "lowercase" => Ok(Self::Lowercase),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 随机部分的最小长度，最小的字母表也有约 20 亿种组合
+const MIN_RANDOM_LENGTH: usize = 6;
+
+/// 随机 key 的生成规则，默认与旧版本一样生成 43 位 base64url 字符
+pub struct TokenConfig {
+    /// 随机部分的长度，`KEY_LENGTH`
+    pub length: usize,
+    /// `KEY_ALPHABET`，可选 `base64url`、`base62`、`base58`、`lowercase`
+    pub alphabet: Alphabet,
+    /// 固定前缀，`KEY_PREFIX`，只能包含字母、数字、`-` 和 `_`
+    pub prefix: String,
+    /// 与已有 key 冲突时的重试次数，`KEY_RETRIES`
+    pub retries: usize,
+}
+
+impl TokenConfig {
+    pub fn from_env() -> Self {
+        let config = Self {
+            length: env_or("KEY_LENGTH", 43),
+            alphabet: env_or("KEY_ALPHABET", Alphabet::Base64Url),
+            prefix: env_or("KEY_PREFIX", String::new()),
+            retries: env_or("KEY_RETRIES", 5),
+        };
+        // 总长度与自定义 key 的限制一致，随机部分太短时容易冲突
+        if config.length < MIN_RANDOM_LENGTH || config.prefix.len() + config.length > 64 {
+            panic!("无效的环境变量 KEY_LENGTH");
+        }
+        if !config
+            .prefix
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            panic!("无效的环境变量 KEY_PREFIX");
+        }
+        config
+    }
+}
+
+/// 按配置生成随机 key
+pub fn new_token(config: &TokenConfig) -> String {
     let rng = SystemRandom::new();
-    let mut random_bytes = vec![0u8; 32];
-    rng.fill(&mut random_bytes)
-        .expect("Failed to generate random bytes");
-    URL_SAFE_NO_PAD.encode(&random_bytes)
+    let chars = config.alphabet.chars();
+    // 拒绝采样，丢弃超出字符集整数倍的字节，保证每个字符概率相同
+    let limit = 256 - 256 % chars.len();
+    let mut token = config.prefix.clone();
+    let mut buf = [0u8; 64];
+    while token.len() < config.prefix.len() + config.length {
+        rng.fill(&mut buf).expect("Failed to generate random bytes");
+        for &b in buf.iter().filter(|&&b| (b as usize) < limit) {
+            if token.len() == config.prefix.len() + config.length {
+                break;
+            }
+            token.push(chars[b as usize % chars.len()] as char);
+        }
+    }
+    token
+}
+
+/// 与现有路由冲突或容易混淆的 key
+const RESERVED: [&str; 14] = [
+    "api", "auth", "key", "user", "import", "export", "admin", "login", "logout", "static",
+    "assets", "health", "url", "urls",
+];
+
+/// 自定义 key 只能包含字母、数字、`-` 和 `_`，长度 3 到 64，且不能是保留字
+pub fn is_valid_custom(key: &str) -> bool {
+    (3..=64).contains(&key.len())
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        && !RESERVED.iter().any(|word| word.eq_ignore_ascii_case(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_token() {
+        let config = TokenConfig {
+            length: 8,
+            alphabet: Alphabet::Base58,
+            prefix: "ev-".to_string(),
+            retries: 0,
+        };
+        let token = new_token(&config);
+        assert_eq!(token.len(), 11);
+        assert!(token.starts_with("ev-"));
+        assert!(token[3..]
+            .bytes()
+            .all(|b| Alphabet::Base58.chars().contains(&b)));
+    }
 }
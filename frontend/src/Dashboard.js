@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { createKey, getKeys } from './api';
+import { createKey, deleteKey, getKeys } from './api';
 import { useNavigate } from 'react-router-dom';
 // import { snackbar } from "mdui/functions/snackbar.js";
 
@@ -25,7 +25,7 @@ const Dashboard = ({ user }) => {
       setLoading(true);
       const response = await createKey();
       const newKey = response.data.data;
-      setKeys([...keys, newKey]);
+      setKeys([...keys, { key: newKey }]);
     } catch (error) {
       console.error('Failed to create key', error);
     } finally {
@@ -33,6 +33,18 @@ const Dashboard = ({ user }) => {
     }
   };
 
+  const handleDeleteKey = async (key) => {
+    if (!window.confirm(`确定删除 ${key} 及其全部链接吗？`)) {
+      return;
+    }
+    try {
+      await deleteKey(key);
+      setKeys(keys.filter((item) => item.key !== key));
+    } catch (error) {
+      console.error('Failed to delete key', error);
+    }
+  };
+
   const handleManageUrls = (key) => {
     navigate(`/add-url/${key}`);
   };
@@ -71,8 +83,9 @@ const Dashboard = ({ user }) => {
         }}
       >
         <mdui-list>
-          {keys.map((key, index) => (
+          {keys.map(({ key, name }, index) => (
             <mdui-list-item key={index} nonclickable>
+              {name && <span style={{ marginRight: "8px" }}>{name}</span>}
               <code><u style={{ fontSize: "16px" }}>{key}</u></code>
               <mdui-button
                 slot="end-icon"
@@ -88,6 +101,13 @@ const Dashboard = ({ user }) => {
                 iconPosition="right"
                 variant="outlined"
               >复制链接</mdui-button>
+              <mdui-button
+                slot="end-icon"
+                onClick={() => handleDeleteKey(key)}
+                icon="delete"
+                iconPosition="right"
+                variant="outlined"
+              >删除</mdui-button>
               {/* 
               <mdui-divider></mdui-divider> */}
             </mdui-list-item>
@@ -25,7 +25,7 @@ export const AddUrl = () => {
         try {
             await addUrl(key, newUrl);
             setNewUrl('');
-            setUrls(urls.concat(newUrl));
+            setUrls(urls.concat({ url: newUrl, weight: 1 }));
         } catch (error) {
             console.error('Failed to add URL', error);
         }
@@ -65,10 +65,11 @@ export const AddUrl = () => {
                 <mdui-list>
                     {urls.map((url, index) => (
                         <mdui-list-item key={index} nonclickable >
-                            <code><u style={{ fontSize: "16px" }}>{url}</u></code>
+                            <code><u style={{ fontSize: "16px" }}>{url.url}</u></code>
+                            <span slot="description">权重: {url.weight}</span>
                             <mdui-button
                                 slot="end-icon"
-                                onClick={() => DeleteUrl(key, url, urls, setUrls)}
+                                onClick={() => DeleteUrl(key, url.url, urls, setUrls)}
                                 icon="link_off"
                                 variant="outlined"
                             >删除</mdui-button>
@@ -109,7 +110,7 @@ const DeleteUrl = async (key, url, urls, setUrls) => {
     try {
         const response = await deleteUrl(key, url);
         if (response.data.code === 0) {
-            setUrls(urls.filter((u) => u !== url));
+            setUrls(urls.filter((u) => u.url !== url));
         }
     } catch (error) {
         console.error('Failed to delete URL', error);